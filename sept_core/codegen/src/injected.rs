@@ -1,5 +1,6 @@
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{quote, ToTokens};
+use syn::spanned::Spanned;
 use syn::Ident;
 
 impl<'a> ToTokens for InjectedBody<'a> {
@@ -9,25 +10,270 @@ impl<'a> ToTokens for InjectedBody<'a> {
             imported_graph_ident,
             fields,
         } = self;
-        for field in fields {
+        for InjectedField { field, name, kind } in fields {
             let ident = &field.ident;
             let ty = &field.ty;
-            let out = quote! {
+            let inject = match (kind, name) {
+                (FieldKind::Lazy, None) => {
+                    stream.extend(quote! { #ident: #graph_ident.inject_lazy(), });
+                    continue;
+                }
+                (FieldKind::Lazy, Some(name)) => {
+                    stream.extend(quote! { #ident: #graph_ident.inject_lazy_named(#name), });
+                    continue;
+                }
+                (FieldKind::Plain, None) => quote! {
+                    .inject::<#ty>(#imported_graph_ident)
+                },
+                (FieldKind::Plain, Some(name)) => quote! {
+                    .inject_named::<#ty>(#name, #imported_graph_ident)
+                },
+                (FieldKind::Optional(inner), None) => quote! {
+                    .inject_optional::<#inner>(#imported_graph_ident)
+                },
+                (FieldKind::Optional(inner), Some(name)) => quote! {
+                    .inject_optional_named::<#inner>(#name, #imported_graph_ident)
+                },
+                (FieldKind::Multi(interface), _) => quote! {
+                    .inject_multi::<#interface>(#imported_graph_ident)
+                },
+                (FieldKind::MultiVec(interface), _) => quote! {
+                    .inject_multi::<#interface>(#imported_graph_ident)
+                    .map(sept::graph::Multi::into_inner)
+                },
+            };
+            stream.extend(quote! {
                 #ident: #graph_ident
-                    .get_node::<#ty>()
-                    .or_else(|| sept::graph::Graph::search_all(#imported_graph_ident))
-                    .unwrap()
-                    .to_owned(),
+                    #inject
+                    .map_err(|err| err.required_by(std::any::type_name::<Self>()))?,
+            });
+        }
+    }
+}
+
+/// The last path segment of `ty` and its first generic type argument, if any.
+fn generic(ty: &syn::Type) -> Option<(&syn::Ident, Option<&syn::Type>)> {
+    let segment = match ty {
+        syn::Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    let inner = match &segment.arguments {
+        syn::PathArguments::AngleBracketed(args) => match args.args.first() {
+            Some(syn::GenericArgument::Type(inner)) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    };
+    Some((&segment.ident, inner))
+}
+
+/// Whether `ty` is spelled out as `sept::graph::Lazy<...>`; other types named
+/// `Lazy` are injected as plain providers unless marked `#[inject(lazy)]`.
+fn is_sept_lazy(ty: &syn::Type) -> bool {
+    let path = match ty {
+        syn::Type::Path(ty) if ty.qself.is_none() => &ty.path,
+        _ => return false,
+    };
+    let idents: Vec<String> = path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    idents == ["sept", "graph", "Lazy"]
+}
+
+/// How a field is injected, derived from its declared type.
+enum FieldKind {
+    Plain,
+    /// `sept::graph::Lazy<T>`, or any field marked `#[inject(lazy)]`, resolved
+    /// after its module is built.
+    Lazy,
+    /// `Option<T>`, `None` when nothing provides `T`.
+    Optional(syn::Type),
+    /// `Multi<I>`, every contribution to `I`.
+    Multi(syn::Type),
+    /// `Vec<Arc<dyn I>>`, every contribution to `I`; other `Vec`s are plain providers.
+    MultiVec(syn::Type),
+}
+
+impl FieldKind {
+    fn of(ty: &syn::Type, lazy: bool) -> Self {
+        if lazy || is_sept_lazy(ty) {
+            return Self::Lazy;
+        }
+        match generic(ty) {
+            Some((ident, Some(inner))) if ident == "Option" => Self::Optional(inner.clone()),
+            Some((ident, Some(inner))) if ident == "Multi" => Self::Multi(inner.clone()),
+            Some((ident, Some(inner))) if ident == "Vec" => match generic(inner) {
+                Some((arc, Some(interface @ syn::Type::TraitObject(_)))) if arc == "Arc" => {
+                    Self::MultiVec(interface.clone())
+                }
+                _ => Self::Plain,
+            },
+            _ => Self::Plain,
+        }
+    }
+}
+
+/// The struct-level `#[injectable(scope = "...", hooks(...))]` arguments.
+#[derive(Default)]
+pub(crate) struct InjectableArgs {
+    scope: Option<TokenStream2>,
+    hooks: Vec<Ident>,
+}
+
+impl InjectableArgs {
+    pub(crate) fn parse(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut args = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("injectable")) {
+            let list = match attr.parse_meta()? {
+                syn::Meta::List(list) => list,
+                meta => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        "Attribute injectable expects arguments!",
+                    ))
+                }
             };
-            stream.extend(out);
+            for nested in list.nested {
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("scope") =>
+                    {
+                        let variant = match &nv.lit {
+                            syn::Lit::Str(lit) if lit.value() == "singleton" => quote!(Singleton),
+                            syn::Lit::Str(lit) if lit.value() == "transient" => quote!(Transient),
+                            syn::Lit::Str(lit) if lit.value() == "request" => quote!(Request),
+                            lit => {
+                                return Err(syn::Error::new_spanned(
+                                    lit,
+                                    "Scope must be \"singleton\", \"transient\" or \"request\".",
+                                ))
+                            }
+                        };
+                        args.scope = Some(quote! {
+                            const SCOPE: sept::graph::Scope = sept::graph::Scope::#variant;
+                        });
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::List(hooks))
+                        if hooks.path.is_ident("hooks") =>
+                    {
+                        for hook in hooks.nested {
+                            args.hooks.push(hook_method(&hook)?);
+                        }
+                    }
+                    arg => {
+                        return Err(syn::Error::new_spanned(arg, "Unknown injectable argument."))
+                    }
+                }
+            }
         }
+        Ok(args)
+    }
+}
+
+/// Maps a lifecycle trait to the `sept::lifecycle::Hooks` method registering it.
+fn hook_method(hook: &syn::NestedMeta) -> syn::Result<Ident> {
+    let method = match hook {
+        syn::NestedMeta::Meta(syn::Meta::Path(path)) => match path.get_ident() {
+            Some(ident) if ident == "OnModuleInit" => "on_module_init",
+            Some(ident) if ident == "OnApplicationBootstrap" => "on_application_bootstrap",
+            Some(ident) if ident == "BeforeApplicationShutdown" => "before_application_shutdown",
+            Some(ident) if ident == "OnApplicationShutdown" => "on_application_shutdown",
+            _ => "",
+        },
+        _ => "",
+    };
+    if method.is_empty() {
+        return Err(syn::Error::new_spanned(
+            hook,
+            "Unknown lifecycle hook, expected OnModuleInit, OnApplicationBootstrap, \
+             BeforeApplicationShutdown or OnApplicationShutdown.",
+        ));
+    }
+    Ok(Ident::new(method, hook.span()))
+}
+
+impl ToTokens for InjectableArgs {
+    fn to_tokens(&self, stream: &mut TokenStream2) {
+        let Self { scope, hooks } = self;
+        stream.extend(quote! { #scope });
+        if !hooks.is_empty() {
+            stream.extend(quote! {
+                fn register_hooks(
+                    value: &std::sync::Arc<Self>,
+                    hooks: &mut sept::lifecycle::Hooks,
+                ) {
+                    #(hooks.#hooks(value.clone());)*
+                }
+            });
+        }
+    }
+}
+
+struct InjectedField {
+    field: syn::Field,
+    name: Option<syn::LitStr>,
+    kind: FieldKind,
+}
+
+impl InjectedField {
+    fn new(field: &syn::Field) -> syn::Result<Self> {
+        let mut name = None;
+        let mut optional = false;
+        let mut lazy = false;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("inject"))
+        {
+            let list = match attr.parse_meta()? {
+                syn::Meta::List(list) => list,
+                meta => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        "Attribute inject expects arguments!",
+                    ))
+                }
+            };
+            for nested in list.nested {
+                match nested {
+                    syn::NestedMeta::Lit(syn::Lit::Str(lit)) => name = Some(lit),
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("optional") => {
+                        optional = true
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("lazy") => {
+                        lazy = true
+                    }
+                    arg => return Err(syn::Error::new_spanned(arg, "Unknown inject argument.")),
+                }
+            }
+        }
+        let kind = FieldKind::of(&field.ty, lazy);
+        if optional && !matches!(kind, FieldKind::Optional(_)) {
+            return Err(syn::Error::new_spanned(
+                &field.ty,
+                "Optional dependencies must be declared as Option<...>.",
+            ));
+        }
+        if name.is_some() && matches!(kind, FieldKind::Multi(_) | FieldKind::MultiVec(_)) {
+            return Err(syn::Error::new_spanned(
+                &field.ty,
+                "Multi-provider dependencies cannot be named.",
+            ));
+        }
+        Ok(Self {
+            field: field.to_owned(),
+            name,
+            kind,
+        })
     }
 }
 
 pub(crate) struct InjectedBody<'a> {
     graph_ident: &'a Ident,
     imported_graph_ident: &'a Ident,
-    fields: Vec<syn::Field>,
+    fields: Vec<InjectedField>,
 }
 
 impl<'a> InjectedBody<'a> {
@@ -40,7 +286,7 @@ impl<'a> InjectedBody<'a> {
         match &data.fields {
             syn::Fields::Named(fl) => {
                 for field in fl.named.iter() {
-                    fields.push(field.to_owned());
+                    fields.push(InjectedField::new(field)?);
                 }
             }
             syn::Fields::Unit => {}
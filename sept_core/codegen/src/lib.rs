@@ -2,14 +2,18 @@ use proc_macro::TokenStream;
 use proc_macro2::Span;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, ToTokens};
-use syn::{parse_macro_input, DeriveInput, Ident, ItemImpl, ItemStruct};
+use syn::{parse_macro_input, DeriveInput, Ident, ItemImpl, ItemStruct, ItemTrait};
 mod args;
 mod injected;
 mod module;
+mod pipe;
 mod route;
-use crate::injected::InjectedBody;
+mod testing;
+use crate::injected::{InjectableArgs, InjectedBody};
 use crate::module::ModuleArgs;
+use crate::pipe::Argument;
 use crate::route::MethodType;
+use crate::testing::GoTestArgs;
 use args::Args;
 use std::str::FromStr;
 
@@ -40,21 +44,19 @@ pub fn go(_: TokenStream, item: TokenStream) -> TokenStream {
     .into()
 }
 
+/// Macro to denote async test functions.
+///
+/// `#[go_test(module = AppModule)]` compiles `AppModule` as a testing module and injects
+/// its providers as the test's arguments, e.g. `async fn it_works(svc: Arc<UserService>)`.
+/// `override_provider(dyn Mailer = mock())` and `override_module(Module = Stub)` replace
+/// providers and modules before it is compiled.
 #[proc_macro_attribute]
-pub fn go_test(_: TokenStream, item: TokenStream) -> TokenStream {
-    let input = syn::parse_macro_input!(item as syn::ItemFn);
+pub fn go_test(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as GoTestArgs);
+    let mut input = syn::parse_macro_input!(item as syn::ItemFn);
 
-    let ret = &input.sig.output;
-    let name = &input.sig.ident;
-    let body = &input.block;
     let attrs = &input.attrs;
-    let mut has_test_attr = false;
-
-    for attr in attrs {
-        if attr.path.is_ident("test") {
-            has_test_attr = true;
-        }
-    }
+    let has_test_attr = attrs.iter().any(|attr| attr.path.is_ident("test"));
 
     if input.sig.asyncness.is_none() {
         return syn::Error::new_spanned(
@@ -65,34 +67,45 @@ pub fn go_test(_: TokenStream, item: TokenStream) -> TokenStream {
         .into();
     }
 
-    let result = if has_test_attr {
-        quote! {
-            #(#attrs)*
-            fn #name() #ret {
-                sept::Runtime::new("test")
-                    .block_on(async { #body })
-            }
-        }
-    } else {
-        quote! {
-            #[test]
-            #(#attrs)*
-            fn #name() #ret {
-                sept::Runtime::new("test")
-                    .block_on(async { #body })
-            }
-        }
+    let body = match args.expand(&input.sig, &input.block) {
+        Ok(body) => body,
+        Err(err) => return err.to_compile_error().into(),
     };
+    let test_attr = (!has_test_attr).then(|| quote! { #[test] });
+    let sig = &mut input.sig;
+    sig.asyncness = None;
+    sig.inputs.clear();
 
-    result.into()
+    (quote! {
+        #test_attr
+        #(#attrs)*
+        #sig {
+            sept::Runtime::new()
+                .block_on(async move { #body })
+        }
+    })
+    .into()
 }
 
 /// Derives the `Injectable` trait for dependency injection.
-#[proc_macro_derive(Injectable)]
+///
+/// `#[injectable(scope = "transient")]` or `#[injectable(scope = "request")]`
+/// changes the provider scope from the default singleton. `Option<_>` fields,
+/// optionally marked `#[inject(optional)]`, are `None` when nothing provides them.
+/// `Multi<dyn Trait>` and `Vec<Arc<dyn Trait>>` fields receive every implementation
+/// contributed with `Module::provide_multi`. `sept::graph::Lazy<_>` fields, or
+/// fields of an imported `Lazy` marked `#[inject(lazy)]`, are filled once the
+/// module is built. `#[injectable(hooks(OnModuleInit, ...))]` registers the
+/// `sept::lifecycle` traits a singleton implements.
+#[proc_macro_derive(Injectable, attributes(inject, injectable))]
 pub fn injectable(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
     let name = &ast.ident;
+    let args = match InjectableArgs::parse(&ast.attrs) {
+        Ok(args) => args,
+        Err(err) => return err.to_compile_error().into(),
+    };
     let graph_ident = Ident::new("graph", Span::call_site());
     let context_ident = Ident::new("ctx", Span::call_site());
     let fields = match &ast.data {
@@ -111,13 +124,14 @@ pub fn injectable(input: TokenStream) -> TokenStream {
                 #[automatically_derived]
                 impl sept::graph::Injected for #name {
                     type Output = Self;
+                    #args
                     fn resolve(
                         #graph_ident: &mut sept::graph::Graph,
                         #context_ident: &[&sept::graph::Graph]
-                    ) -> Self {
-                        Self {
+                    ) -> Result<Self, sept::error::SeptError> {
+                        Ok(Self {
                             #f
-                        }
+                        })
                     }
                 }
             };
@@ -127,16 +141,69 @@ pub fn injectable(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Declares the error an `impl ExceptionFilter` catches, e.g. `#[catch(HttpException)]`.
+#[proc_macro_attribute]
+pub fn catch(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let exception = parse_macro_input!(attr as syn::Type);
+    let mut input = parse_macro_input!(item as ItemImpl);
+    if input.trait_.is_none() {
+        return syn::Error::new_spanned(&input.self_ty, "Expected `impl ExceptionFilter for ...`.")
+            .to_compile_error()
+            .into();
+    }
+    input.items.push(syn::parse_quote! {
+        type Exception = #exception;
+    });
+    TokenStream::from(quote! { #input })
+}
+
+/// Lets `Arc<dyn Trait>` be bound to any implementation with `Module::provide_as`.
 #[proc_macro_attribute]
-pub fn module(_: TokenStream, item: TokenStream) -> TokenStream {
+pub fn interface(_: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as ItemTrait);
+    if !input.generics.params.is_empty() {
+        return syn::Error::new_spanned(&input.generics, "Generic traits are not supported.")
+            .to_compile_error()
+            .into();
+    }
+    let name = &input.ident;
+    let expanded = quote! {
+        #input
+
+        #[automatically_derived]
+        impl<T: #name + 'static> sept::graph::Interface<T> for dyn #name {
+            fn upcast(value: std::sync::Arc<T>) -> std::sync::Arc<Self> {
+                value
+            }
+        }
+    };
+    TokenStream::from(expanded)
+}
+
+/// Generates `ModuleFactory` from the `imports`, `exports`, `providers` and `clients` attributes.
+///
+/// `#[module(global)]` makes the module's exports visible to every module without importing it.
+/// `#[guard(AuthGuard)]`, `#[interceptor(Timing)]`, `#[pipe(TrimPipe)]` and
+/// `#[filter(NotFoundFilter)]` apply to every route of the module's clients.
+#[proc_macro_attribute]
+pub fn module(args: TokenStream, item: TokenStream) -> TokenStream {
     let mut input = parse_macro_input!(item as ItemStruct);
     let name = &input.ident;
+    let global = match module::parse_global(args.into()) {
+        Ok(true) => Some(quote! { .global() }),
+        Ok(false) => None,
+        Err(err) => return err.to_compile_error().into(),
+    };
     match ModuleArgs::parse_and_strip(&mut input.attrs) {
         Ok(ModuleArgs {
             clients,
             imports,
             exports,
             providers,
+            guards,
+            interceptors,
+            pipes,
+            filters,
         }) => {
             let expanded = quote! {
                 #input
@@ -145,10 +212,15 @@ pub fn module(_: TokenStream, item: TokenStream) -> TokenStream {
                 impl sept::sept_module::ModuleFactory for #name {
                     fn get_module() -> sept::sept_module::Module {
                         sept::sept_module::Module::new()
-                            #(.import::<#imports>())*
+                            #global
+                            #(#imports)*
                             #(.export::<#exports>())*
-                            #(.provide::<#providers>())*
+                            #(#providers)*
                             #(.client::<#clients>())*
+                            #(.guard::<#guards>())*
+                            #(.interceptor::<#interceptors>())*
+                            #(.pipe::<#pipes>())*
+                            #(.filter::<#filters>())*
                     }
                 }
             };
@@ -158,15 +230,60 @@ pub fn module(_: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
+/// Removes the `#[<name>(...)]` attributes from `attrs`, e.g. `#[guard(...)]`, returning the paths they list.
+fn strip_list(attrs: &mut Vec<syn::Attribute>, name: &str) -> syn::Result<Vec<syn::Path>> {
+    let mut paths = Vec::new();
+    let mut retained = Vec::new();
+    for attr in attrs.drain(..) {
+        if attr.path.is_ident(name) {
+            paths.extend(module::parse_list(&attr)?);
+        } else {
+            retained.push(attr);
+        }
+    }
+    *attrs = retained;
+    Ok(paths)
+}
+
 struct Method {
     name: Ident,
     method_type: MethodType,
     args: Args,
+    /// The handler as `Client::method`, for route metadata and guards.
+    handler: String,
+    /// The client's `#[guard(...)]`s, then the handler's.
+    guards: Vec<syn::Path>,
+    /// The client's `#[interceptor(...)]`s, then the handler's.
+    interceptors: Vec<syn::Path>,
+    /// The client's `#[pipe(...)]`s, then the handler's, run over every extracted argument.
+    pipes: Vec<syn::Path>,
+    /// The handler's `#[filter(...)]`s, then the client's.
+    filters: Vec<syn::Path>,
+    /// The typed arguments of the handler and their pipes.
+    arguments: Vec<Argument>,
     impl_item: syn::ImplItemMethod,
 }
 
 impl Method {
-    fn new(impl_item: &mut syn::ImplItemMethod) -> Result<Option<Self>, syn::Error> {
+    fn new(
+        impl_item: &mut syn::ImplItemMethod,
+        ty: &syn::Type,
+    ) -> Result<Option<Self>, syn::Error> {
+        let guards = strip_list(&mut impl_item.attrs, "guard")?;
+        let interceptors = strip_list(&mut impl_item.attrs, "interceptor")?;
+        let pipes = strip_list(&mut impl_item.attrs, "pipe")?;
+        let filters = strip_list(&mut impl_item.attrs, "filter")?;
+        let mut arguments = Vec::new();
+        for input in &mut impl_item.sig.inputs {
+            if let syn::FnArg::Typed(arg) = input {
+                arguments.push(Argument::new(arg)?);
+            }
+        }
+        let enhanced = !guards.is_empty()
+            || !interceptors.is_empty()
+            || !pipes.is_empty()
+            || !filters.is_empty()
+            || arguments.iter().any(|argument| !argument.pipes.is_empty());
         let mut method_type = None;
         let mut args = None;
         let mut err = None;
@@ -174,7 +291,7 @@ impl Method {
             match attr.parse_meta() {
                 Ok(syn::Meta::List(list)) => {
                     if let Some(ident) = list.path.get_ident() {
-                        if let Ok(mt) = MethodType::from_str(&*ident.to_string()) {
+                        if let Ok(mt) = MethodType::from_str(&ident.to_string()) {
                             method_type = Some(mt);
                             match Args::new(list.nested.into_iter().collect()) {
                                 Ok(ar) => {
@@ -188,7 +305,7 @@ impl Method {
                 }
                 Ok(syn::Meta::Path(path)) => {
                     if let Some(ident) = path.get_ident() {
-                        if let Ok(mt) = MethodType::from_str(&*ident.to_string()) {
+                        if let Ok(mt) = MethodType::from_str(&ident.to_string()) {
                             method_type = Some(mt);
                             return false;
                         }
@@ -209,13 +326,62 @@ impl Method {
                 name: format_ident!("_{}_{}_", "sept", impl_item.sig.ident),
                 method_type: mt,
                 args: args.unwrap_or_default(),
+                handler: format!("{}::{}", source_name(ty), impl_item.sig.ident),
+                guards,
+                interceptors,
+                pipes,
+                filters,
+                arguments,
                 impl_item: impl_item.clone(),
             })),
+            None if enhanced => Err(syn::Error::new_spanned(
+                &impl_item.sig,
+                "Guards, interceptors, filters and pipes can only be applied to route handlers.",
+            )),
             None => Ok(None),
         }
     }
 }
 
+/// Renders a type or path as written, without the spaces `quote` inserts.
+fn source_name(tokens: &impl ToTokens) -> String {
+    tokens.to_token_stream().to_string().replace(' ', "")
+}
+
+impl Method {
+    /// The `sept::inspect::RouteInfo` of this handler, served under `client`.
+    fn route_info(&self, client: &Args) -> TokenStream2 {
+        let method = self.method_type.as_str();
+        let (scope, path) = (client.path.value(), self.args.path.value());
+        let path = match (scope.strip_suffix('/'), path.starts_with('/')) {
+            (Some(scope), true) => format!("{}{}", scope, path),
+            _ if scope.is_empty() && path.is_empty() => String::from("/"),
+            _ => format!("{}{}", scope, path),
+        };
+        let handler = &self.handler;
+        let wrappers = client
+            .wrappers
+            .iter()
+            .chain(&self.args.wrappers)
+            .map(source_name);
+        let guards = client
+            .methods
+            .iter()
+            .chain(&self.args.methods)
+            .chain(&self.guards)
+            .map(source_name);
+        quote! {
+            sept::inspect::RouteInfo {
+                method: #method,
+                path: #path,
+                handler: #handler,
+                wrappers: vec![#(#wrappers),*],
+                guards: vec![#(#guards),*],
+            }
+        }
+    }
+}
+
 impl ToTokens for Method {
     fn to_tokens(&self, stream: &mut TokenStream2) {
         let Self {
@@ -227,32 +393,136 @@ impl ToTokens for Method {
                     methods,
                     wrappers,
                 },
+            handler,
+            guards,
+            interceptors,
+            pipes,
+            filters,
+            arguments,
             impl_item,
         } = self;
         let target = &impl_item.sig.ident;
+        if let Some(syn::FnArg::Receiver(receiver)) = impl_item.sig.inputs.first() {
+            let err = syn::Error::new_spanned(receiver, "Handlers cannot take self.");
+            stream.extend(err.to_compile_error());
+            return;
+        }
+        let args: Vec<_> = (0..arguments.len())
+            .map(|i| format_ident!("arg{}", i))
+            .collect();
+        let types = arguments.iter().map(Argument::extracted_type);
+        let resolve_pipes = arguments
+            .iter()
+            .enumerate()
+            .map(|(i, argument)| argument.resolve(i));
+        let clone_pipes = arguments
+            .iter()
+            .enumerate()
+            .map(|(i, argument)| argument.clone_pipes(i));
+        let argument_pipes = arguments.iter().any(Argument::is_extracted).then(|| {
+            quote! {
+                let argument_pipes = sept::pipe::ArgumentPipes::new(
+                    module,
+                    vec![#(sept::pipe::get_argument_pipe::<#pipes>(module)),*],
+                );
+            }
+        });
+        let clone_argument_pipes = argument_pipes
+            .is_some()
+            .then(|| quote! { let argument_pipes = argument_pipes.clone(); });
+        let apply_pipes = arguments
+            .iter()
+            .zip(&args)
+            .enumerate()
+            .map(|(i, (argument, arg))| argument.apply(i, arg, handler));
         let expanded = quote! {
             #[allow(non_snake_case)]
-            fn #name(&self) -> actix_web::Resource {
+            fn #name(
+                &self,
+                module: &sept::sept_module::ModuleRef,
+            ) -> impl actix_web::dev::HttpServiceFactory {
+                let interceptors = sept::interceptor::Interceptors::new(
+                    module,
+                    #handler,
+                    vec![#(sept::interceptor::get::<#interceptors>(module)),*],
+                );
+                #(#resolve_pipes)*
+                #argument_pipes
                 actix_web::web::resource(#path)
                     .guard(actix_web::guard::#method_type())
                     #(.guard(actix_web::guard::fn_guard(#methods)))*
+                    .wrap(sept::guard::Guards::new(
+                        module,
+                        #handler,
+                        vec![#(sept::guard::get::<#guards>(module)),*],
+                    ))
                     #(.wrap(#wrappers))*
-                    .to(Self::#target)
+                    .wrap(sept::filter::ExceptionFilters::new(
+                        module,
+                        #handler,
+                        vec![#(sept::filter::get::<#filters>(module)),*],
+                    ))
+                    .to(move |request: actix_web::HttpRequest, #(#args: #types),*| {
+                        let interceptors = interceptors.clone();
+                        #(#clone_pipes)*
+                        #clone_argument_pipes
+                        async move {
+                            interceptors
+                                .intercept(request, move || {
+                                    Box::pin(async move {
+                                        #(#apply_pipes)*
+                                        let handled = Self::#target(#(#args),*).await;
+                                        Result::<_, sept::error::SeptError>::Ok(
+                                            sept::interceptor::Handled::new(handled),
+                                        )
+                                    })
+                                })
+                                .await
+                        }
+                    })
             }
         };
         stream.extend(expanded)
     }
 }
 
+/// Serves the annotated handlers under a scope, generating `ServiceFactory`.
+///
+/// `#[guard(AuthGuard)]` below `#[client]`, or on a handler, runs `sept::guard::Guard`s
+/// before the handlers, after those of the client's module. `#[interceptor(Timing)]`
+/// likewise wraps the handlers in `sept::interceptor::Interceptor`s, and
+/// `#[filter(NotFoundFilter)]` catches their errors with `sept::filter::ExceptionFilter`s.
+/// `#[pipe(ParseUuidPipe)]` on an argument runs `sept::pipe::Pipe`s over it before the handler
+/// is called; below `#[client]` or on a handler, `#[pipe(TrimPipe)]` runs
+/// `sept::pipe::ArgumentPipe`s over every path, query and body argument after the module's.
 #[proc_macro_attribute]
 pub fn client(attr: TokenStream, item: TokenStream) -> TokenStream {
     let parsed = parse_macro_input!(attr as syn::AttributeArgs);
     let mut input = parse_macro_input!(item as ItemImpl);
+    let (client_guards, client_interceptors, client_pipes, client_filters) = match (
+        strip_list(&mut input.attrs, "guard"),
+        strip_list(&mut input.attrs, "interceptor"),
+        strip_list(&mut input.attrs, "pipe"),
+        strip_list(&mut input.attrs, "filter"),
+    ) {
+        (Ok(guards), Ok(interceptors), Ok(pipes), Ok(filters)) => {
+            (guards, interceptors, pipes, filters)
+        }
+        (Err(err), ..) | (_, Err(err), ..) | (_, _, Err(err), _) | (.., Err(err)) => {
+            return err.to_compile_error().into()
+        }
+    };
     let mut handlers = Vec::new();
     for item in &mut input.items {
         if let syn::ImplItem::Method(ref mut item_method) = item {
-            match Method::new(item_method) {
-                Ok(Some(method)) => {
+            match Method::new(item_method, &input.self_ty) {
+                Ok(Some(mut method)) => {
+                    method.guards.splice(0..0, client_guards.iter().cloned());
+                    method
+                        .interceptors
+                        .splice(0..0, client_interceptors.iter().cloned());
+                    method.pipes.splice(0..0, client_pipes.iter().cloned());
+                    method.filters.extend(client_filters.iter().cloned());
                     handlers.push(method);
                 }
                 Ok(None) => {}
@@ -264,12 +534,28 @@ pub fn client(attr: TokenStream, item: TokenStream) -> TokenStream {
     }
 
     match args::Args::new(parsed) {
-        Ok(args::Args {
-            path,
-            methods,
-            wrappers,
-        }) => {
+        Ok(args) => {
+            let routes: Vec<_> = handlers
+                .iter()
+                .map(|handler| handler.route_info(&args))
+                .collect();
+            let args::Args {
+                path,
+                methods,
+                wrappers,
+            } = args;
             let route_idents: Vec<&syn::Ident> = handlers.iter().map(|x| &x.name).collect();
+            let guards = handlers.iter().flat_map(|handler| &handler.guards);
+            let interceptors = handlers.iter().flat_map(|handler| &handler.interceptors);
+            let filters = handlers.iter().flat_map(|handler| &handler.filters);
+            let pipes = handlers.iter().flat_map(|handler| {
+                handler.pipes.iter().chain(
+                    handler
+                        .arguments
+                        .iter()
+                        .flat_map(|argument| &argument.pipes),
+                )
+            });
             let name = &input.self_ty;
 
             let expanded = quote! {
@@ -287,22 +573,49 @@ pub fn client(attr: TokenStream, item: TokenStream) -> TokenStream {
                     fn from_request(req: &actix_web::HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
                         match req.app_data::<actix_web::web::Data<#name>>() {
                             Some(st) => futures_util::future::ok(st.get_ref().clone()),
-                            None => panic!("Failed to extract data class."),
+                            None => futures_util::future::err(
+                                actix_web::error::ErrorInternalServerError("Failed to extract data class.")
+                            ),
                         }
                     }
                 }
 
                 #[automatically_derived]
                 impl sept::sept_module::ServiceFactory for #name {
-                    fn register(&self, app: &mut actix_web::web::ServiceConfig) {
+                    fn register(
+                        &self,
+                        app: &mut actix_web::web::ServiceConfig,
+                        module: &sept::sept_module::ModuleRef,
+                    ) {
                         app.service(
                             actix_web::web::scope(#path)
                             .app_data(actix_web::web::Data::new(self.clone()))
+                            .app_data(actix_web::web::Data::new(module.clone()))
                             #(.guard(actix_web::guard::fn_guard(#methods)))*
                             #(.wrap(#wrappers))*
-                            #(.service(Self::#route_idents(&self)))*
+                            #(.service(Self::#route_idents(&self, module)))*
                         );
                     }
+
+                    fn routes(&self) -> Vec<sept::inspect::RouteInfo> {
+                        vec![#(#routes),*]
+                    }
+
+                    fn guards(&self) -> Vec<sept::guard::GuardFactory> {
+                        vec![#(sept::guard::resolve::<#guards>),*]
+                    }
+
+                    fn interceptors(&self) -> Vec<sept::interceptor::InterceptorFactory> {
+                        vec![#(sept::interceptor::resolve::<#interceptors>),*]
+                    }
+
+                    fn pipes(&self) -> Vec<sept::pipe::PipeFactory> {
+                        vec![#(sept::pipe::resolve::<#pipes>),*]
+                    }
+
+                    fn filters(&self) -> Vec<sept::filter::FilterFactory> {
+                        vec![#(sept::filter::resolve::<#filters>),*]
+                    }
                 }
             };
             TokenStream::from(expanded)
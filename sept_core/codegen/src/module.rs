@@ -1,12 +1,127 @@
-use proc_macro2::{Ident, Span};
-use std::collections::HashMap;
-use syn::Meta;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::Token;
 
 pub(crate) struct ModuleArgs {
     pub(crate) clients: Vec<syn::Path>,
-    pub(crate) imports: Vec<syn::Path>,
-    pub(crate) exports: Vec<syn::Path>,
-    pub(crate) providers: Vec<syn::Path>,
+    pub(crate) imports: Vec<Import>,
+    pub(crate) exports: Vec<syn::Type>,
+    pub(crate) providers: Vec<Provider>,
+    pub(crate) guards: Vec<syn::Path>,
+    pub(crate) interceptors: Vec<syn::Path>,
+    pub(crate) pipes: Vec<syn::Path>,
+    pub(crate) filters: Vec<syn::Path>,
+}
+
+/// An entry of `#[imports(...)]`: `Module`, `forward_ref(Module)` or an
+/// expression building a dynamic module, e.g. `DbModule::for_root(options())`.
+pub(crate) enum Import {
+    Module(syn::Path),
+    ForwardRef(syn::Path),
+    Dynamic(Box<syn::Expr>),
+}
+
+impl Parse for Import {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let expr: syn::Expr = input.parse()?;
+        match expr {
+            syn::Expr::Path(path) if path.qself.is_none() => Ok(Self::Module(path.path)),
+            syn::Expr::Call(call) if is_forward_ref(&call) => match call.args.first() {
+                Some(syn::Expr::Path(path)) if call.args.len() == 1 => {
+                    Ok(Self::ForwardRef(path.path.clone()))
+                }
+                _ => Err(syn::Error::new_spanned(
+                    call.args,
+                    "forward_ref expects a single module.",
+                )),
+            },
+            expr => Ok(Self::Dynamic(Box::new(expr))),
+        }
+    }
+}
+
+fn is_forward_ref(call: &syn::ExprCall) -> bool {
+    matches!(&*call.func, syn::Expr::Path(func) if func.path.is_ident("forward_ref"))
+}
+
+impl ToTokens for Import {
+    fn to_tokens(&self, stream: &mut TokenStream2) {
+        stream.extend(match self {
+            Self::Module(path) => quote! { .import::<#path>() },
+            Self::ForwardRef(path) => quote! { .forward_ref::<#path>() },
+            Self::Dynamic(expr) => quote! { .import_dynamic(#expr) },
+        });
+    }
+}
+
+/// An entry of `#[providers(...)]`: `Provider`, `Implementation as dyn Trait`,
+/// `factory(function)` or `async_factory(function)`.
+pub(crate) enum Provider {
+    Class(syn::Path),
+    Factory(syn::Path),
+    AsyncFactory(syn::Path),
+    Bound {
+        implementation: syn::Path,
+        interface: Box<syn::Type>,
+    },
+}
+
+impl Parse for Provider {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let implementation: syn::Path = input.parse()?;
+        if input.peek(syn::token::Paren) {
+            let content;
+            if implementation.is_ident("factory") {
+                syn::parenthesized!(content in input);
+                return Ok(Self::Factory(content.parse()?));
+            }
+            if implementation.is_ident("async_factory") {
+                syn::parenthesized!(content in input);
+                return Ok(Self::AsyncFactory(content.parse()?));
+            }
+        }
+        if input.peek(Token![as]) {
+            input.parse::<Token![as]>()?;
+            return Ok(Self::Bound {
+                implementation,
+                interface: input.parse()?,
+            });
+        }
+        Ok(Self::Class(implementation))
+    }
+}
+
+impl ToTokens for Provider {
+    fn to_tokens(&self, stream: &mut TokenStream2) {
+        stream.extend(match self {
+            Self::Class(path) => quote! { .provide::<#path>() },
+            Self::Factory(path) => quote! { .provide_factory(#path) },
+            Self::AsyncFactory(path) => quote! { .provide_async_factory(#path) },
+            Self::Bound {
+                implementation,
+                interface,
+            } => quote! { .provide_as::<#interface, #implementation>() },
+        });
+    }
+}
+
+/// Parses the arguments of `#[module(...)]`, returning whether the module is global.
+pub(crate) fn parse_global(args: TokenStream2) -> syn::Result<bool> {
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let ident: syn::Ident = syn::parse2(args)?;
+    if ident != "global" {
+        return Err(syn::Error::new_spanned(ident, "Unknown module argument."));
+    }
+    Ok(true)
+}
+
+pub(crate) fn parse_list<T: Parse>(attr: &syn::Attribute) -> syn::Result<Vec<T>> {
+    let items = attr.parse_args_with(Punctuated::<T, Token![,]>::parse_terminated)?;
+    Ok(items.into_iter().collect())
 }
 
 impl ModuleArgs {
@@ -15,56 +130,41 @@ impl ModuleArgs {
         let mut imports = Vec::new();
         let mut exports = Vec::new();
         let mut providers = Vec::new();
-        let mut path_to_vec = HashMap::new();
-        let call_site = Span::call_site();
-        path_to_vec.insert(Ident::new("clients", call_site), &mut clients);
-        path_to_vec.insert(Ident::new("imports", call_site), &mut imports);
-        path_to_vec.insert(Ident::new("exports", call_site), &mut exports);
-        path_to_vec.insert(Ident::new("providers", call_site), &mut providers);
-        for attr in attrs.clone() {
-            match attr.parse_meta() {
-                Ok(Meta::List(nv)) => {
-                    if let Some(vec) = nv.path.get_ident().and_then(|x| path_to_vec.get_mut(x)) {
-                        for item in nv.nested {
-                            if let syn::NestedMeta::Meta(syn::Meta::Path(path)) = item {
-                                vec.push(path);
-                            } else {
-                                return Err(syn::Error::new_spanned(
-                                    nv.path,
-                                    "Attribute guard expects a path!",
-                                ));
-                            }
-                        }
-                    } else {
-                        return Err(syn::Error::new_spanned(
-                            nv.path,
-                            "Unknown attribute key is specified.",
-                        ));
-                    }
-                }
-                Ok(Meta::Path(path)) => {
-                    return Err(syn::Error::new_spanned(
-                        path,
-                        "Unknown attribute key is specified.",
-                    ))
+        let mut guards = Vec::new();
+        let mut interceptors = Vec::new();
+        let mut pipes = Vec::new();
+        let mut filters = Vec::new();
+        let mut retained = Vec::new();
+        for attr in attrs.drain(..) {
+            let key = match attr.path.get_ident() {
+                Some(ident) => ident.to_string(),
+                None => {
+                    retained.push(attr);
+                    continue;
                 }
-                Ok(arg) => {
-                    return Err(syn::Error::new_spanned(arg, "Unknown attribute."));
-                }
-                Err(_) => {}
+            };
+            match key.as_str() {
+                "clients" => clients.extend(parse_list(&attr)?),
+                "imports" => imports.extend(parse_list(&attr)?),
+                "exports" => exports.extend(parse_list(&attr)?),
+                "providers" => providers.extend(parse_list(&attr)?),
+                "guard" => guards.extend(parse_list(&attr)?),
+                "interceptor" => interceptors.extend(parse_list(&attr)?),
+                "pipe" => pipes.extend(parse_list(&attr)?),
+                "filter" => filters.extend(parse_list(&attr)?),
+                _ => retained.push(attr),
             }
         }
-        attrs.retain(|attr| {
-            attr.path
-                .get_ident()
-                .and_then(|x| path_to_vec.get(x))
-                .is_none()
-        });
+        *attrs = retained;
         Ok(Self {
             clients,
             imports,
             exports,
             providers,
+            guards,
+            interceptors,
+            pipes,
+            filters,
         })
     }
 }
@@ -0,0 +1,158 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, ToTokens};
+
+/// Where a handler argument is extracted from, mirroring `sept::pipe::ArgumentKind`.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Param,
+    Query,
+    Body,
+    Custom,
+}
+
+impl Kind {
+    fn of(ty: &syn::Type) -> Self {
+        let ident = match ty {
+            syn::Type::Path(ty) => ty.path.segments.last().map(|segment| &segment.ident),
+            _ => None,
+        };
+        match ident {
+            Some(ident) if ident == "Path" => Kind::Param,
+            Some(ident) if ident == "Query" => Kind::Query,
+            Some(ident) if ident == "Json" || ident == "Form" => Kind::Body,
+            _ => Kind::Custom,
+        }
+    }
+}
+
+impl ToTokens for Kind {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        tokens.extend(match self {
+            Kind::Param => quote! { sept::pipe::ArgumentKind::Param },
+            Kind::Query => quote! { sept::pipe::ArgumentKind::Query },
+            Kind::Body => quote! { sept::pipe::ArgumentKind::Body },
+            Kind::Custom => quote! { sept::pipe::ArgumentKind::Custom },
+        })
+    }
+}
+
+/// A handler argument and the pipes transforming it before the handler is called.
+pub(crate) struct Argument {
+    name: String,
+    kind: Kind,
+    ty: syn::Type,
+    /// The argument's `#[pipe(...)]`s.
+    pub(crate) pipes: Vec<syn::Path>,
+}
+
+impl Argument {
+    /// Removes the `#[pipe(...)]` attributes of `arg`.
+    pub(crate) fn new(arg: &mut syn::PatType) -> syn::Result<Self> {
+        Ok(Self {
+            name: crate::source_name(&arg.pat),
+            kind: Kind::of(&arg.ty),
+            ty: (*arg.ty).clone(),
+            pipes: crate::strip_list(&mut arg.attrs, "pipe")?,
+        })
+    }
+
+    /// Whether the argument pipes of the route apply to this argument.
+    pub(crate) fn is_extracted(&self) -> bool {
+        self.kind != Kind::Custom
+    }
+
+    /// The pipe instances of the argument at `index`, named as in [`Argument::apply`].
+    fn idents(&self, index: usize) -> Vec<syn::Ident> {
+        (0..self.pipes.len())
+            .map(|pipe| format_ident!("pipe_{}_{}", index, pipe))
+            .collect()
+    }
+
+    /// Takes the pipes of the argument at `index` resolved with `module`, once per route.
+    pub(crate) fn resolve(&self, index: usize) -> TokenStream2 {
+        let idents = self.idents(index);
+        let pipes = &self.pipes;
+        quote! {
+            #(let #idents = sept::pipe::get::<#pipes>(module);)*
+        }
+    }
+
+    /// Clones the pipes of the argument at `index` into a request.
+    pub(crate) fn clone_pipes(&self, index: usize) -> TokenStream2 {
+        let idents = self.idents(index);
+        quote! {
+            #(let #idents = #idents.clone();)*
+        }
+    }
+
+    /// The type the argument is extracted as, left to the pipes to infer if it has any.
+    pub(crate) fn extracted_type(&self) -> TokenStream2 {
+        if self.pipes.is_empty() {
+            return self.ty.to_token_stream();
+        }
+        match (&self.ty, self.kind) {
+            (syn::Type::Path(ty), Kind::Param | Kind::Query | Kind::Body) => {
+                let mut ty = ty.clone();
+                if let Some(segment) = ty.path.segments.last_mut() {
+                    segment.arguments = syn::PathArguments::AngleBracketed(syn::parse_quote!(<_>));
+                }
+                ty.to_token_stream()
+            }
+            _ => quote! { _ },
+        }
+    }
+
+    /// Runs the pipes over `arg`, the argument at `index`, rebinding it as its declared type,
+    /// then the route's `argument_pipes` if it is extracted.
+    pub(crate) fn apply(&self, index: usize, arg: &syn::Ident, handler: &str) -> TokenStream2 {
+        if self.pipes.is_empty() && !self.is_extracted() {
+            return TokenStream2::new();
+        }
+        let Self { name, kind, ty, .. } = self;
+        let idents = self.idents(index);
+        let transforms = quote! {
+            #(let value = sept::pipe::Pipe::transform(&*#idents, value, &metadata)?;)*
+        };
+        let piped = if self.pipes.is_empty() {
+            TokenStream2::new()
+        } else if self.is_extracted() {
+            quote! {
+                let #arg: #ty = sept::pipe::Extracted::pipe(#arg, |value| {
+                    #transforms
+                    Ok(value)
+                })?;
+            }
+        } else {
+            quote! {
+                let #arg: #ty = {
+                    let value = #arg;
+                    #transforms
+                    value
+                };
+            }
+        };
+        // The validator is looked up here, where the argument's type is known.
+        let argument_piped = self.is_extracted().then(|| {
+            quote! {
+                let #arg: #ty = sept::pipe::Extracted::pipe(#arg, |mut value| {
+                    use sept::pipe::{ViaValidate as _, WithoutValidate as _};
+                    let validator = (&&sept::pipe::Probe::<
+                        <#ty as sept::pipe::Extracted>::Inner,
+                    >(std::marker::PhantomData))
+                        .validator();
+                    argument_pipes.transform(&mut value, validator, &metadata)?;
+                    Ok(value)
+                })?;
+            }
+        });
+        quote! {
+            let metadata = sept::pipe::ArgumentMetadata {
+                handler: #handler,
+                name: #name,
+                kind: #kind,
+            };
+            #piped
+            #argument_piped
+        }
+    }
+}
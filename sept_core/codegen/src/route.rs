@@ -17,6 +17,21 @@ pub(crate) enum MethodType {
 }
 
 impl MethodType {
+    /// The HTTP method name, e.g. `GET`.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            MethodType::Get => "GET",
+            MethodType::Post => "POST",
+            MethodType::Put => "PUT",
+            MethodType::Delete => "DELETE",
+            MethodType::Head => "HEAD",
+            MethodType::Connect => "CONNECT",
+            MethodType::Options => "OPTIONS",
+            MethodType::Trace => "TRACE",
+            MethodType::Patch => "PATCH",
+        }
+    }
+
     fn as_guard(&self) -> &'static str {
         match self {
             MethodType::Get => "Get",
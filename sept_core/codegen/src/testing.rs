@@ -0,0 +1,138 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::Token;
+
+/// The `#[go_test(module = AppModule, override_provider(...), override_module(...))]` arguments.
+#[derive(Default)]
+pub(crate) struct GoTestArgs {
+    pub(crate) module: Option<syn::Path>,
+    overrides: Vec<Override>,
+}
+
+/// An entry of `override_provider(Provider = value)` or `override_module(Module = Replacement)`.
+enum Override {
+    Provider(Box<syn::Type>, Box<syn::Expr>),
+    Module(syn::Path, syn::Path),
+}
+
+impl Parse for GoTestArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = Self::default();
+        while !input.is_empty() {
+            let ident: syn::Ident = input.parse()?;
+            if ident == "module" {
+                input.parse::<Token![=]>()?;
+                args.module = Some(input.parse()?);
+            } else if ident == "override_provider" {
+                let content;
+                syn::parenthesized!(content in input);
+                let entries =
+                    Punctuated::<ProviderOverride, Token![,]>::parse_terminated(&content)?;
+                for ProviderOverride(provider, value) in entries {
+                    args.overrides
+                        .push(Override::Provider(Box::new(provider), Box::new(value)));
+                }
+            } else if ident == "override_module" {
+                let content;
+                syn::parenthesized!(content in input);
+                let entries = Punctuated::<ModuleOverride, Token![,]>::parse_terminated(&content)?;
+                for ModuleOverride(module, replacement) in entries {
+                    args.overrides.push(Override::Module(module, replacement));
+                }
+            } else {
+                return Err(syn::Error::new_spanned(ident, "Unknown go_test argument."));
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        if args.module.is_none() && !args.overrides.is_empty() {
+            return Err(input.error("Overrides require `module = ...`."));
+        }
+        Ok(args)
+    }
+}
+
+struct ProviderOverride(syn::Type, syn::Expr);
+
+impl Parse for ProviderOverride {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let provider = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(Self(provider, input.parse()?))
+    }
+}
+
+struct ModuleOverride(syn::Path, syn::Path);
+
+impl Parse for ModuleOverride {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let module = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(Self(module, input.parse()?))
+    }
+}
+
+impl GoTestArgs {
+    /// Builds the testing module and binds every argument of `sig` to its provider around `body`.
+    pub(crate) fn expand(
+        &self,
+        sig: &syn::Signature,
+        body: &syn::Block,
+    ) -> syn::Result<TokenStream2> {
+        let module = match &self.module {
+            Some(module) => module,
+            None if sig.inputs.is_empty() => return Ok(quote! { #body }),
+            None => {
+                return Err(syn::Error::new_spanned(
+                    &sig.inputs,
+                    "Test arguments require `module = ...`.",
+                ))
+            }
+        };
+        let mut bindings = Vec::new();
+        for input in &sig.inputs {
+            match input {
+                syn::FnArg::Typed(arg) => {
+                    let (pat, ty) = (&arg.pat, &arg.ty);
+                    bindings.push(quote! {
+                        let #pat: #ty = sept_test_context
+                            .get::<#ty>()
+                            .unwrap_or_else(|err| panic!("{}", err));
+                    });
+                }
+                syn::FnArg::Receiver(receiver) => {
+                    return Err(syn::Error::new_spanned(receiver, "Tests cannot take self."))
+                }
+            }
+        }
+        let overrides = self.overrides.iter().map(|entry| match entry {
+            Override::Provider(provider, value) => quote! {
+                .override_provider::<#provider>().use_value(#value)
+            },
+            Override::Module(module, replacement) => quote! {
+                .override_module::<#module, #replacement>()
+            },
+        });
+        let output = match &sig.output {
+            syn::ReturnType::Default => quote! { () },
+            syn::ReturnType::Type(_, ty) => quote! { #ty },
+        };
+        Ok(quote! {
+            let sept_test_context = sept::testing::Test::create_testing_module::<#module>()
+                #(#overrides)*
+                .compile()
+                .await
+                .unwrap_or_else(|err| panic!("{}", err));
+            #(#bindings)*
+            let result: #output = async move #body.await;
+            sept_test_context
+                .close()
+                .await
+                .unwrap_or_else(|err| panic!("{}", err));
+            result
+        })
+    }
+}
@@ -0,0 +1,250 @@
+use crate::exception::{HttpException, ProblemDetails};
+use actix_web::http::StatusCode;
+use std::fmt;
+
+/// Errors raised while building the module graph or starting the application.
+#[derive(Debug)]
+pub enum SeptError {
+    /// A dependency was requested that no module in scope provides.
+    MissingProvider {
+        type_name: &'static str,
+        name: Option<&'static str>,
+        module: Option<&'static str>,
+        chain: Vec<&'static str>,
+    },
+    /// A module imports itself through a chain of other modules.
+    CircularImport {
+        path: Vec<&'static str>,
+    },
+    /// A dynamic module was imported twice with the same key but different options.
+    DynamicModuleConflict {
+        name: &'static str,
+        key: String,
+    },
+    /// A provider override of a testing module replaced no provider of the module graph.
+    UnusedOverride {
+        type_name: &'static str,
+        name: Option<&'static str>,
+    },
+    /// A provider depends on itself through a chain of other providers.
+    CircularDependency {
+        path: Vec<&'static str>,
+    },
+    /// A request-scoped provider was injected outside of a request, e.g. into a singleton.
+    RequestScoped {
+        type_name: &'static str,
+        chain: Vec<&'static str>,
+    },
+    /// A fallible factory provider returned an error.
+    Factory {
+        type_name: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A lifecycle hook of a provider returned an error.
+    Lifecycle {
+        hook: &'static str,
+        type_name: &'static str,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// A guard, pipe or interceptor rejected a request, answered like the exception itself.
+    Http(HttpException),
+    /// The module graph was not built within `SeptConfig::startup_timeout`.
+    StartupTimeout(std::time::Duration),
+    Io(std::io::Error),
+}
+
+impl SeptError {
+    pub fn missing<T: ?Sized>() -> Self {
+        Self::MissingProvider {
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            module: None,
+            chain: Vec::new(),
+        }
+    }
+
+    pub fn missing_named<T: ?Sized>(name: &'static str) -> Self {
+        Self::MissingProvider {
+            type_name: std::any::type_name::<T>(),
+            name: Some(name),
+            module: None,
+            chain: Vec::new(),
+        }
+    }
+
+    /// Records the provider whose construction needed the failing dependency.
+    pub fn required_by(mut self, type_name: &'static str) -> Self {
+        match &mut self {
+            Self::MissingProvider { chain, .. } | Self::RequestScoped { chain, .. } => {
+                chain.push(type_name)
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Converts the error for actix, unwrapping `Http` so filters can catch the `HttpException`.
+    pub(crate) fn into_error(self) -> actix_web::Error {
+        match self {
+            Self::Http(exception) => exception.into(),
+            err => err.into(),
+        }
+    }
+
+    /// Records the module the error was raised in, keeping the innermost one.
+    pub fn in_module(mut self, name: &'static str) -> Self {
+        if let Self::MissingProvider {
+            module: module @ None,
+            ..
+        } = &mut self
+        {
+            *module = Some(name);
+        }
+        self
+    }
+}
+
+impl fmt::Display for SeptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingProvider {
+                type_name,
+                name,
+                module,
+                chain,
+            } => {
+                write!(f, "no provider for `{}`", type_name)?;
+                if let Some(name) = name {
+                    write!(f, " named {:?}", name)?;
+                }
+                if let Some(module) = module {
+                    write!(f, " in module `{}`", module)?;
+                }
+                for provider in chain {
+                    write!(f, "\n  required by `{}`", provider)?;
+                }
+                Ok(())
+            }
+            Self::CircularImport { path } => write!(
+                f,
+                "circular module import: {}\n  use `forward_ref` to break an intentional cycle",
+                path.join(" -> ")
+            ),
+            Self::DynamicModuleConflict { name, key } => write!(
+                f,
+                "dynamic module `{}` is imported with key {:?} and different options\n  give each configuration its own key with `DynamicModule::keyed`",
+                name, key
+            ),
+            Self::UnusedOverride { type_name, name } => {
+                write!(f, "override of `{}`", type_name)?;
+                if let Some(name) = name {
+                    write!(f, " named {:?}", name)?;
+                }
+                write!(
+                    f,
+                    " replaces no provider\n  multi-providers cannot be overridden, and values must be overridden with their provided type"
+                )
+            }
+            Self::CircularDependency { path } => write!(
+                f,
+                "circular provider dependency: {}\n  inject one side as `sept::graph::Lazy<Arc<T>>` to break an intentional cycle",
+                path.join(" -> ")
+            ),
+            Self::RequestScoped { type_name, chain } => {
+                write!(
+                    f,
+                    "`{}` is request-scoped and cannot be injected outside of a request",
+                    type_name
+                )?;
+                for provider in chain {
+                    write!(f, "\n  required by `{}`", provider)?;
+                }
+                Ok(())
+            }
+            Self::Factory { type_name, source } => {
+                write!(f, "factory for `{}` failed: {}", type_name, source)
+            }
+            Self::Lifecycle {
+                hook,
+                type_name,
+                source,
+            } => write!(f, "`{}` of `{}` failed: {}", hook, type_name, source),
+            Self::Http(exception) => exception.fmt(f),
+            Self::StartupTimeout(timeout) => {
+                write!(f, "module graph was not built within {:?}", timeout)
+            }
+            Self::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SeptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Factory { source, .. } | Self::Lifecycle { source, .. } => Some(&**source),
+            Self::Io(err) => Some(err),
+            Self::Http(exception) => Some(exception),
+            _ => None,
+        }
+    }
+}
+
+impl From<HttpException> for SeptError {
+    fn from(exception: HttpException) -> Self {
+        Self::Http(exception)
+    }
+}
+
+impl From<std::io::Error> for SeptError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl actix_web::ResponseError for SeptError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Http(exception) => exception.status(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> actix_web::HttpResponse {
+        match self {
+            Self::Http(exception) => exception.error_response(),
+            // The message names internal types and providers, so it is only logged.
+            _ => {
+                tracing::error!("{}", self);
+                ProblemDetails::new(self.status_code()).response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::ResponseError;
+
+    #[actix_rt::test]
+    async fn test_internal_errors_are_answered_without_their_message() {
+        let response = SeptError::missing::<std::sync::Arc<u32>>().error_response();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        let body = actix_web::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            problem,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Internal Server Error",
+                "status": 500,
+            })
+        );
+    }
+}
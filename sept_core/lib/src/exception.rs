@@ -0,0 +1,331 @@
+use crate::error::SeptError;
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// The header a request's ID is read from, see [`ProblemDetails::for_request`].
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The members of [`ProblemDetails`] an extension must not replace.
+const RESERVED_MEMBERS: [&str; 6] = ["type", "title", "status", "detail", "instance", "requestId"];
+
+/// An RFC 7807 problem document, served as `application/problem+json`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProblemDetails {
+    /// A URI identifying the problem type, `about:blank` by default.
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    /// The reason phrase of `status`.
+    pub title: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The path of the request the problem occurred in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Extension members, serialized next to the standard ones.
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl ProblemDetails {
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            type_uri: String::from("about:blank"),
+            title: status
+                .canonical_reason()
+                .unwrap_or("Unknown Status")
+                .to_owned(),
+            status: status.as_u16(),
+            detail: None,
+            instance: None,
+            request_id: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Sets `instance` to the path of `request`, and `requestId` to its `x-request-id` header.
+    pub fn for_request(mut self, request: &HttpRequest) -> Self {
+        self.instance = Some(request.path().to_owned());
+        self.request_id = request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .map(str::to_owned);
+        self
+    }
+
+    pub fn response(&self) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let body = serde_json::to_string(self).expect("problem details serialize to JSON");
+        HttpResponse::build(status)
+            .insert_header((
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/problem+json"),
+            ))
+            .body(body)
+    }
+}
+
+/// An error answered with its status and a [`ProblemDetails`] body.
+///
+/// Usually created as one of [`BadRequest`], [`Unauthorized`], [`Forbidden`],
+/// [`NotFound`], [`Conflict`] or [`UnprocessableEntity`], which convert into
+/// it, so handlers can return `Result<_, HttpException>` and use `?`, and
+/// filters can catch every one of them with `#[catch(HttpException)]`. They
+/// also convert into `SeptError`, for guards, pipes and interceptors to reject
+/// requests with. Routes of `#[client]`s add the request's path and ID to the
+/// body.
+#[derive(Clone, Debug)]
+pub struct HttpException {
+    status: StatusCode,
+    message: String,
+    type_uri: Option<String>,
+    details: Map<String, Value>,
+}
+
+impl HttpException {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            type_uri: None,
+            details: Map::new(),
+        }
+    }
+
+    /// Identifies the problem type with a URI, instead of `about:blank`.
+    pub fn with_type(mut self, uri: impl Into<String>) -> Self {
+        self.type_uri = Some(uri.into());
+        self
+    }
+
+    /// Adds an extension member to the problem details.
+    ///
+    /// A `key` naming a standard member, `type`, `title`, `status`, `detail`,
+    /// `instance` or `requestId`, would replace it, so it is logged and ignored.
+    pub fn with_detail(mut self, key: impl Into<String>, value: impl Serialize) -> Self {
+        let key = key.into();
+        if RESERVED_MEMBERS.contains(&key.as_str()) {
+            tracing::warn!(
+                "`{}` is a standard problem details member, not an extension",
+                key
+            );
+            return self;
+        }
+        let value = serde_json::to_value(value).unwrap_or(Value::Null);
+        self.details.insert(key, value);
+        self
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn details(&self) -> &Map<String, Value> {
+        &self.details
+    }
+
+    pub fn problem(&self) -> ProblemDetails {
+        let mut problem = ProblemDetails::new(self.status);
+        if let Some(type_uri) = &self.type_uri {
+            problem.type_uri = type_uri.clone();
+        }
+        problem.detail = Some(self.message.clone());
+        problem.extensions = self.details.clone();
+        problem
+    }
+}
+
+impl fmt::Display for HttpException {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for HttpException {}
+
+impl ResponseError for HttpException {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.problem().response()
+    }
+}
+
+/// Declares an [`HttpException`] with a fixed status.
+macro_rules! exceptions {
+    ($($(#[$doc:meta])* $exception:ident => $status:ident,)+) => {$(
+        $(#[$doc])*
+        #[derive(Clone, Debug)]
+        pub struct $exception(HttpException);
+
+        impl $exception {
+            pub fn new(message: impl Into<String>) -> Self {
+                Self(HttpException::new(StatusCode::$status, message))
+            }
+
+            /// See [`HttpException::with_type`].
+            pub fn with_type(self, uri: impl Into<String>) -> Self {
+                Self(self.0.with_type(uri))
+            }
+
+            /// See [`HttpException::with_detail`].
+            pub fn with_detail(self, key: impl Into<String>, value: impl Serialize) -> Self {
+                Self(self.0.with_detail(key, value))
+            }
+        }
+
+        impl fmt::Display for $exception {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl From<$exception> for HttpException {
+            fn from(exception: $exception) -> Self {
+                exception.0
+            }
+        }
+
+        impl From<$exception> for SeptError {
+            fn from(exception: $exception) -> Self {
+                SeptError::Http(exception.0)
+            }
+        }
+
+        impl From<$exception> for actix_web::Error {
+            fn from(exception: $exception) -> Self {
+                exception.0.into()
+            }
+        }
+    )+};
+}
+
+exceptions! {
+    /// 400 Bad Request
+    BadRequest => BAD_REQUEST,
+    /// 401 Unauthorized
+    Unauthorized => UNAUTHORIZED,
+    /// 403 Forbidden
+    Forbidden => FORBIDDEN,
+    /// 404 Not Found
+    NotFound => NOT_FOUND,
+    /// 409 Conflict
+    Conflict => CONFLICT,
+    /// 422 Unprocessable Entity
+    UnprocessableEntity => UNPROCESSABLE_ENTITY,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_application::SeptApplication;
+    use crate::testing::TestApp;
+    use crate::Injectable;
+
+    #[derive(Clone, Injectable)]
+    struct AccountsClient;
+
+    #[crate::client("/accounts")]
+    impl AccountsClient {
+        #[get("/{id}")]
+        async fn show(
+            _client: Self,
+            id: actix_web::web::Path<u32>,
+        ) -> Result<String, HttpException> {
+            match id.into_inner() {
+                0 => Err(Forbidden::new("The root account is hidden"))?,
+                1 => Ok(String::from("alice")),
+                id => Err(NotFound::new(format!("Account {} does not exist", id))
+                    .with_detail("id", id)
+                    .into()),
+            }
+        }
+
+        #[post("/{name}")]
+        async fn create(
+            _client: Self,
+            name: actix_web::web::Path<String>,
+        ) -> Result<String, Conflict> {
+            Err(Conflict::new(format!("`{}` is taken", name))
+                .with_type("https://example.com/problems/taken"))
+        }
+    }
+
+    #[crate::module]
+    #[clients(AccountsClient)]
+    struct AccountsModule;
+
+    #[test]
+    fn test_http_exception_ignores_standard_members_as_details() {
+        let exception: HttpException = NotFound::new("gone")
+            .with_detail("status", 200)
+            .with_detail("id", 7)
+            .into();
+        assert_eq!(exception.problem().status, 404);
+        assert_eq!(
+            serde_json::Value::Object(exception.details().clone()),
+            serde_json::json!({ "id": 7 })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_http_exceptions_respond_with_problem_details() {
+        let app = TestApp::create::<AccountsModule>(SeptApplication::default())
+            .await
+            .unwrap();
+        app.get("/accounts/1").send().await.assert_status(200);
+        let missing = app
+            .get("/accounts/7")
+            .header(("x-request-id", "req-42"))
+            .send()
+            .await
+            .assert_status(404);
+        assert_eq!(
+            missing.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        assert_eq!(
+            missing.json::<serde_json::Value>(),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Account 7 does not exist",
+                "instance": "/accounts/7",
+                "requestId": "req-42",
+                "id": 7,
+            })
+        );
+        let hidden = app.get("/accounts/0").send().await.assert_status(403);
+        assert_eq!(
+            hidden.json::<serde_json::Value>()["detail"],
+            "The root account is hidden"
+        );
+        let taken = app.post("/accounts/bob").send().await.assert_status(409);
+        assert_eq!(
+            taken.json::<serde_json::Value>(),
+            serde_json::json!({
+                "type": "https://example.com/problems/taken",
+                "title": "Conflict",
+                "status": 409,
+                "detail": "`bob` is taken",
+                "instance": "/accounts/bob",
+            })
+        );
+    }
+}
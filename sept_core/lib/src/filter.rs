@@ -0,0 +1,465 @@
+use crate::error::SeptError;
+use crate::exception::{HttpException, ProblemDetails};
+use crate::graph::Injected;
+use crate::guard::ExecutionContext;
+use crate::sept_module::ModuleRef;
+use actix_web::body::{BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{HttpResponse, ResponseError};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use futures_util::FutureExt;
+use std::any::Any;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Turns the errors of type `Exception` raised while serving a request into a response.
+///
+/// The caught type is declared with `#[catch(HttpException)]` on the impl.
+/// Filters are attached with `#[filter(ProblemFilter)]` on a handler, a
+/// `#[client]` impl or a `#[module]`, or to every route with
+/// `SeptApplication::use_global_filters`, and the first one catching an error
+/// in that order handles it. They catch errors returned by handlers, guards,
+/// interceptors and pipes, as well as panics as [`Panic`]. Errors no filter
+/// catches keep the response of their `ResponseError` impl, headers included;
+/// only `HttpException`s, panics and errors raised outside of handlers, e.g. by
+/// guards, are answered with [`ProblemDetails`] by the default filter, which
+/// hides the message of server errors other than `HttpException`s. Filters
+/// are resolved like [`Guard`](crate::guard::Guard)s.
+#[allow(async_fn_in_trait)]
+pub trait ExceptionFilter {
+    type Exception: ResponseError + 'static;
+
+    async fn catch(&self, exception: &Self::Exception, context: &ExecutionContext) -> HttpResponse;
+}
+
+/// A panic raised while serving a request, answered with 500 Internal Server Error.
+#[derive(Debug)]
+pub struct Panic {
+    message: String,
+}
+
+impl Panic {
+    fn new(payload: Box<dyn Any + Send>) -> Self {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => (*message).to_owned(),
+                Err(_) => String::from("Box<dyn Any>"),
+            },
+        };
+        Self { message }
+    }
+
+    /// The message the handler panicked with.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Panic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "panicked: {}", self.message)
+    }
+}
+
+impl ResponseError for Panic {}
+
+/// An error raised while serving a request, or the error response it was turned into.
+pub enum Caught {
+    Error(actix_web::Error),
+    Response(HttpResponse),
+}
+
+impl Caught {
+    pub fn error(&self) -> &actix_web::Error {
+        match self {
+            Self::Error(error) => error,
+            Self::Response(response) => response.error().expect("only error responses are caught"),
+        }
+    }
+}
+
+/// A resolved filter, returning `None` for errors it does not catch, see [`resolve`].
+pub type FilterFn = Arc<
+    dyn Fn(Rc<Caught>, Rc<ExecutionContext>) -> Option<LocalBoxFuture<'static, HttpResponse>>
+        + Send
+        + Sync,
+>;
+
+/// Resolves a filter from a module, see [`resolve`].
+pub type FilterFactory = fn(&mut ModuleRef) -> Result<FilterFn, SeptError>;
+
+/// Injects the filter `F` from `module`, or builds it from the module's providers.
+pub fn resolve<F>(module: &mut ModuleRef) -> Result<FilterFn, SeptError>
+where
+    F: ExceptionFilter + Injected<Output = F> + Send + Sync + 'static,
+{
+    module.resolve_enhancer::<F>()?;
+    Ok(get::<F>(module))
+}
+
+/// The filter `F` resolved when `module` was built, for the routes of its clients.
+pub fn get<F>(module: &ModuleRef) -> FilterFn
+where
+    F: ExceptionFilter + Send + Sync + 'static,
+{
+    let filter = module.enhancer::<F>();
+    Arc::new(move |caught, context| {
+        caught.error().as_error::<F::Exception>()?;
+        let filter = filter.clone();
+        Some(Box::pin(async move {
+            let exception = caught
+                .error()
+                .as_error::<F::Exception>()
+                .expect("the filter catches the error");
+            filter.catch(exception, &context).await
+        }))
+    })
+}
+
+/// An exception filter or a tuple of them, see `SeptApplication::use_global_filters`.
+pub trait FilterSet {
+    fn factories() -> Vec<FilterFactory>;
+}
+
+impl<F> FilterSet for F
+where
+    F: ExceptionFilter + Injected<Output = F> + Send + Sync + 'static,
+{
+    fn factories() -> Vec<FilterFactory> {
+        vec![resolve::<F>]
+    }
+}
+
+macro_rules! filters {
+    ($($filter:ident),+) => {
+        impl<$($filter),+> FilterSet for ($($filter,)+)
+        where
+            $($filter: ExceptionFilter + Injected<Output = $filter> + Send + Sync + 'static),+
+        {
+            fn factories() -> Vec<FilterFactory> {
+                vec![$(resolve::<$filter>),+]
+            }
+        }
+    };
+}
+
+filters!(A, B);
+filters!(A, B, C);
+filters!(A, B, C, D);
+filters!(A, B, C, D, E);
+
+/// Answers errors no filter caught.
+///
+/// Error responses pass through unchanged, except those of `HttpException`s,
+/// which get the request's path and ID. Bare errors, `SeptError`s and panics
+/// are answered with [`ProblemDetails`], hiding the message of server errors.
+fn default_response(caught: Rc<Caught>, context: &ExecutionContext) -> HttpResponse {
+    if let Some(exception) = caught.error().as_error::<HttpException>() {
+        return exception
+            .problem()
+            .for_request(context.request())
+            .response();
+    }
+    let internal = caught.error().as_error::<SeptError>().is_some();
+    let caught = match Rc::try_unwrap(caught) {
+        Ok(Caught::Response(response)) if !internal => return response,
+        Ok(caught) => Rc::new(caught),
+        // Kept by a filter that did not catch it, so only the error is left to answer with.
+        Err(caught) => caught,
+    };
+    let error = caught.error();
+    let status = error.as_response_error().status_code();
+    let mut problem = ProblemDetails::new(status);
+    if status.is_server_error() {
+        // A `SeptError` response, e.g. of an extractor, logged its error already.
+        if let Caught::Error(_) = &*caught {
+            tracing::error!("{} failed: {}", context.handler(), error);
+        }
+    } else {
+        problem.detail = Some(error.to_string());
+    }
+    problem.for_request(context.request()).response()
+}
+
+/// Middleware running the filters of a route over its errors, generated by `#[client]`.
+pub struct ExceptionFilters {
+    handler: &'static str,
+    filters: Rc<Vec<FilterFn>>,
+}
+
+impl ExceptionFilters {
+    /// Tries `filters`, then the filters of `module`, for the route served by `handler`.
+    pub fn new(module: &ModuleRef, handler: &'static str, filters: Vec<FilterFn>) -> Self {
+        let filters = filters
+            .into_iter()
+            .chain(module.filters().iter().cloned())
+            .collect();
+        Self {
+            handler,
+            filters: Rc::new(filters),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ExceptionFilters
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
+    type Error = actix_web::Error;
+    type Transform = ExceptionFiltersMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ExceptionFiltersMiddleware {
+            service: Rc::new(service),
+            handler: self.handler,
+            filters: self.filters.clone(),
+        }))
+    }
+}
+
+pub struct ExceptionFiltersMiddleware<S> {
+    service: Rc<S>,
+    handler: &'static str,
+    filters: Rc<Vec<FilterFn>>,
+}
+
+impl<S, B> Service<ServiceRequest> for ExceptionFiltersMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B, BoxBody>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let filters = self.filters.clone();
+        let handler = self.handler;
+        Box::pin(async move {
+            let request = req.request().clone();
+            let served = AssertUnwindSafe(async move { service.call(req).await })
+                .catch_unwind()
+                .await;
+            let caught = match served {
+                Ok(Ok(response)) if response.response().error().is_none() => {
+                    return Ok(response.map_into_left_body())
+                }
+                Ok(Ok(response)) => Caught::Response(response.into_parts().1.map_into_boxed_body()),
+                Ok(Err(error)) => Caught::Error(error),
+                Err(panic) => Caught::Error(Panic::new(panic).into()),
+            };
+            let caught = Rc::new(caught);
+            let context = Rc::new(ExecutionContext::new(request.clone(), handler));
+            let filtered = filters
+                .iter()
+                .find_map(|filter| filter(caught.clone(), context.clone()));
+            let response = match filtered {
+                Some(response) => response.await,
+                None => default_response(caught, &context),
+            };
+            Ok(ServiceResponse::new(request, response).map_into_right_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_application::SeptApplication;
+    use crate::testing::TestApp;
+    use crate::Injectable;
+
+    #[derive(Debug)]
+    struct Missing(u32);
+
+    impl std::fmt::Display for Missing {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "no entry {}", self.0)
+        }
+    }
+
+    impl actix_web::ResponseError for Missing {
+        fn status_code(&self) -> actix_web::http::StatusCode {
+            actix_web::http::StatusCode::NOT_FOUND
+        }
+    }
+
+    #[derive(Debug)]
+    struct Throttled;
+
+    impl std::fmt::Display for Throttled {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("throttled")
+        }
+    }
+
+    impl actix_web::ResponseError for Throttled {
+        fn status_code(&self) -> actix_web::http::StatusCode {
+            actix_web::http::StatusCode::TOO_MANY_REQUESTS
+        }
+
+        fn error_response(&self) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::TooManyRequests()
+                .insert_header((actix_web::http::header::RETRY_AFTER, "30"))
+                .body("slow down")
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ErrorLog;
+
+    #[derive(Clone, Injectable)]
+    struct MissingFilter {
+        _log: Arc<ErrorLog>,
+    }
+
+    #[crate::catch(Missing)]
+    impl ExceptionFilter for MissingFilter {
+        async fn catch(
+            &self,
+            exception: &Missing,
+            context: &ExecutionContext,
+        ) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::NotFound().json(serde_json::json!({
+                "id": exception.0,
+                "handler": context.handler(),
+            }))
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct GoneFilter;
+
+    #[crate::catch(Missing)]
+    impl ExceptionFilter for GoneFilter {
+        async fn catch(
+            &self,
+            exception: &Missing,
+            _: &ExecutionContext,
+        ) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::Gone().body(exception.to_string())
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct HttpExceptionFilter;
+
+    #[crate::catch(HttpException)]
+    impl ExceptionFilter for HttpExceptionFilter {
+        async fn catch(
+            &self,
+            exception: &HttpException,
+            _: &ExecutionContext,
+        ) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::build(exception.status())
+                .json(serde_json::json!({ "error": exception.message() }))
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ClosedGuard;
+
+    impl crate::guard::Guard for ClosedGuard {
+        async fn can_activate(&self, _: &ExecutionContext) -> Result<bool, SeptError> {
+            Ok(false)
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ArchiveClient;
+
+    #[crate::client("/archive")]
+    impl ArchiveClient {
+        #[get("/legacy/{id}")]
+        async fn legacy(_client: Self, id: actix_web::web::Path<u32>) -> Result<String, Missing> {
+            Err(Missing(id.into_inner()))
+        }
+
+        #[get("/closed")]
+        #[guard(ClosedGuard)]
+        async fn closed(_client: Self) -> String {
+            String::from("open")
+        }
+
+        #[get("/taken")]
+        async fn taken(_client: Self) -> actix_web::Result<String> {
+            Err(actix_web::error::ErrorConflict("taken"))
+        }
+
+        #[get("/throttled")]
+        async fn throttled(_client: Self) -> Result<String, Throttled> {
+            Err(Throttled)
+        }
+
+        #[get("/crash")]
+        async fn crash(_client: Self) -> String {
+            panic!("archive is corrupted")
+        }
+
+        #[get("/{id}")]
+        #[filter(MissingFilter)]
+        async fn show(_client: Self, id: actix_web::web::Path<u32>) -> Result<String, Missing> {
+            match id.into_inner() {
+                1 => Ok(String::from("first")),
+                id => Err(Missing(id)),
+            }
+        }
+    }
+
+    #[crate::module]
+    #[providers(ErrorLog)]
+    #[clients(ArchiveClient)]
+    #[filter(HttpExceptionFilter)]
+    struct ArchiveModule;
+
+    #[actix_rt::test]
+    async fn test_filters_catch_errors_from_handler_to_application() {
+        let application =
+            SeptApplication::default().use_global_filters::<(GoneFilter, HttpExceptionFilter)>();
+        let app = TestApp::create::<ArchiveModule>(application).await.unwrap();
+        let shown = app.get("/archive/1").send().await.assert_status(200);
+        assert_eq!(shown.text(), "first");
+        let missing = app.get("/archive/2").send().await.assert_status(404);
+        assert_eq!(
+            missing.json::<serde_json::Value>(),
+            serde_json::json!({ "id": 2, "handler": "ArchiveClient::show" })
+        );
+        let gone = app.get("/archive/legacy/3").send().await.assert_status(410);
+        assert_eq!(gone.text(), "no entry 3");
+        let closed = app.get("/archive/closed").send().await.assert_status(403);
+        assert_eq!(
+            closed.json::<serde_json::Value>(),
+            serde_json::json!({ "error": "Forbidden resource" })
+        );
+        let taken = app.get("/archive/taken").send().await.assert_status(409);
+        assert_eq!(taken.text(), "taken");
+        let throttled = app
+            .get("/archive/throttled")
+            .send()
+            .await
+            .assert_status(429);
+        assert_eq!(throttled.headers().get("retry-after").unwrap(), "30");
+        assert_eq!(throttled.text(), "slow down");
+        let crashed = app.get("/archive/crash").send().await.assert_status(500);
+        assert_eq!(
+            crashed.json::<serde_json::Value>(),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Internal Server Error",
+                "status": 500,
+                "instance": "/archive/crash",
+            })
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use crate::error::SeptError;
+use crate::lifecycle::Hooks;
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
 
 #[derive(Debug)]
 pub struct Value<T>(pub T);
@@ -19,98 +22,1083 @@ impl<T: Clone> Clone for Value<T> {
     }
 }
 
+/// How long a provider instance lives.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scope {
+    /// One instance per module, built when the module is built.
+    #[default]
+    Singleton,
+    /// A new instance every time the provider is injected.
+    Transient,
+    /// One instance per HTTP request, built the first time the request needs it.
+    Request,
+}
+
 pub trait Injected: Send + Sync {
     type Output: Injected;
-    fn resolve(graph: &mut Graph, imported_graphs: &[&Graph]) -> Self::Output
+    const SCOPE: Scope = Scope::Singleton;
+    fn resolve(graph: &mut Graph, imported_graphs: &[&Graph]) -> Result<Self::Output, SeptError>
     where
         Self: Sized;
+
+    /// Registers the lifecycle hooks of a singleton, see `#[injectable(hooks(...))]`.
+    fn register_hooks(_value: &Arc<Self::Output>, _hooks: &mut Hooks)
+    where
+        Self: Sized,
+    {
+    }
 }
 
 impl<T: Send + Sync> Injected for Value<T> {
     type Output = Self;
-    fn resolve(_graph: &mut Graph, _imported_graphs: &[&Graph]) -> Self::Output {
-        panic!(
-            "Data type has not been provided: {}",
-            std::any::type_name::<T>()
-        )
+    fn resolve(_graph: &mut Graph, _imported_graphs: &[&Graph]) -> Result<Self, SeptError> {
+        Err(SeptError::missing::<Self>())
     }
 }
 
 impl<T: Injected<Output = T>> Injected for Arc<T> {
     type Output = Self;
-    fn resolve(graph: &mut Graph, imported_graphs: &[&Graph]) -> Self::Output {
-        T::resolve(graph, imported_graphs).into()
+    const SCOPE: Scope = T::SCOPE;
+    fn resolve(graph: &mut Graph, imported_graphs: &[&Graph]) -> Result<Self, SeptError> {
+        T::resolve(graph, imported_graphs).map(Self::new)
+    }
+
+    fn register_hooks(value: &Arc<Self>, hooks: &mut Hooks) {
+        T::register_hooks(value, hooks)
+    }
+}
+
+/// Binds the trait object `Self` to the implementation `T`.
+///
+/// Implemented by `#[sept::interface]` for `dyn Trait` and every `T: Trait`.
+pub trait Interface<T> {
+    fn upcast(value: Arc<T>) -> Arc<Self>;
+}
+
+/// A dependency that is resolved once the module owning it has been built.
+///
+/// Injecting `sept::graph::Lazy<Arc<T>>` instead of `Arc<T>` breaks a provider
+/// cycle; the value must not be accessed from inside `Injected::resolve`. A
+/// field naming it through an import must be marked `#[inject(lazy)]`.
+/// Providers built after their module, e.g. request-scoped ones, get it filled
+/// once the injection that built them completes.
+pub struct Lazy<T>(Arc<OnceLock<T>>);
+
+impl<T> Lazy<T> {
+    pub fn get(&self) -> Option<&T> {
+        self.0.get()
+    }
+}
+
+impl<T> std::ops::Deref for Lazy<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.get().unwrap_or_else(|| {
+            panic!(
+                "Lazy dependency accessed before its module was built: {}",
+                std::any::type_name::<T>()
+            )
+        })
+    }
+}
+
+impl<T> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Lazy").field(&self.0.get()).finish()
+    }
+}
+
+/// Every implementation of `I` contributed with `Module::provide_multi`
+/// by a module and its imports.
+pub struct Multi<I: ?Sized>(Vec<Arc<I>>);
+
+impl<I: ?Sized> Multi<I> {
+    pub fn into_inner(self) -> Vec<Arc<I>> {
+        self.0
+    }
+}
+
+impl<I: ?Sized> std::ops::Deref for Multi<I> {
+    type Target = [Arc<I>];
+
+    fn deref(&self) -> &[Arc<I>] {
+        &self.0
+    }
+}
+
+impl<I: ?Sized> Clone for Multi<I> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<'a, I: ?Sized> IntoIterator for &'a Multi<I> {
+    type Item = &'a Arc<I>;
+    type IntoIter = std::slice::Iter<'a, Arc<I>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Keys the contribution of `T` to the multi-provider `I`.
+struct Contribution<I: ?Sized, T>(std::marker::PhantomData<fn(&I) -> T>);
+
+/// A tuple of dependencies injected into a factory provider.
+pub trait Dependencies: Sized {
+    fn inject(graph: &mut Graph, imports: &[&Graph]) -> Result<Self, SeptError>;
+}
+
+macro_rules! impl_dependencies {
+    ($($dep:ident),*) => {
+        impl<$($dep: Clone + 'static),*> Dependencies for ($($dep,)*) {
+            #[allow(unused_variables)]
+            fn inject(graph: &mut Graph, imports: &[&Graph]) -> Result<Self, SeptError> {
+                Ok(($(graph.inject::<$dep>(imports)?,)*))
+            }
+        }
+    };
+}
+
+impl_dependencies!();
+impl_dependencies!(A);
+impl_dependencies!(A, B);
+impl_dependencies!(A, B, C);
+impl_dependencies!(A, B, C, D);
+impl_dependencies!(A, B, C, D, E);
+impl_dependencies!(A, B, C, D, E, F);
+impl_dependencies!(A, B, C, D, E, F, G);
+impl_dependencies!(A, B, C, D, E, F, G, H);
+
+type Node = Arc<dyn Send + Sync + Any>;
+type Factory = Arc<dyn Fn(&mut Graph, &[&Graph]) -> Result<Node, SeptError> + Send + Sync>;
+type Registrar = Arc<dyn Fn(&Node, &mut Hooks) + Send + Sync>;
+
+/// The key a node is stored under: its type and an optional name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Token {
+    id: TypeId,
+    type_name: &'static str,
+    name: Option<&'static str>,
+}
+
+impl Token {
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            name: None,
+        }
+    }
+
+    pub fn named<T: ?Sized + 'static>(name: &'static str) -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            name: Some(name),
+        }
+    }
+
+    /// The name of the type stored under this token.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The name given with `Module::provide_named`, if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    fn missing<T: ?Sized>(&self) -> SeptError {
+        match self.name {
+            Some(name) => SeptError::missing_named::<T>(name),
+            None => SeptError::missing::<T>(),
+        }
+    }
+}
+
+/// A provider that has been declared but not constructed yet.
+#[derive(Clone)]
+struct Pending {
+    token: Token,
+    name: &'static str,
+    scope: Scope,
+    factory: Factory,
+}
+
+impl fmt::Debug for Pending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+trait LazySlot: Send + Sync {
+    fn token(&self) -> Token;
+
+    fn fill(&self, graph: &mut Graph, imports: &[&Graph]) -> Result<(), SeptError>;
+}
+
+struct Slot<T> {
+    token: Token,
+    cell: Arc<OnceLock<T>>,
+}
+
+impl<T: Clone + Send + Sync + 'static> LazySlot for Slot<T> {
+    fn token(&self) -> Token {
+        self.token
+    }
+
+    fn fill(&self, graph: &mut Graph, imports: &[&Graph]) -> Result<(), SeptError> {
+        let value = graph.inject_token::<T>(self.token, imports)?;
+        let _ = self.cell.set(value);
+        Ok(())
     }
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Default)]
 pub struct Graph {
-    map: HashMap<TypeId, Arc<(dyn Send + Sync + Any)>>,
+    map: HashMap<Token, Node>,
+    pending: Vec<Pending>,
+    resolving: Vec<(Token, &'static str)>,
+    lazy: Vec<Arc<dyn LazySlot>>,
+    /// Contributions to multi-providers, keyed by the `TypeId` of the interface.
+    multi: Vec<(TypeId, Token)>,
+    /// Lifecycle hooks of the singletons constructed in this graph.
+    hooks: Hooks,
+    /// Registers the hooks of providers that are not `Injected`, see [`Graph::on_provided`].
+    registrars: Vec<(Token, Registrar)>,
+    /// Whether this graph belongs to a single request and may hold request-scoped providers.
+    request: bool,
+}
+
+impl fmt::Debug for Graph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Graph")
+            .field("map", &self.map)
+            .field("pending", &self.pending)
+            .finish()
+    }
 }
 
 impl Graph {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            pending: Vec::new(),
+            resolving: Vec::new(),
+            lazy: Vec::new(),
+            multi: Vec::new(),
+            hooks: Hooks::default(),
+            registrars: Vec::new(),
+            request: false,
+        }
+    }
+
+    /// An empty graph for a single request, building the request-scoped and
+    /// transient providers declared in the graphs it injects from.
+    pub fn for_request() -> Self {
+        Self {
+            request: true,
+            ..Self::new()
         }
     }
 
-    pub fn filter_by(&self, set: std::collections::HashSet<TypeId>) -> Self {
-        let mut new = self.clone();
-        new.map.retain(|&k, _| set.contains(&k));
+    pub fn filter_by(&self, set: HashSet<Token>) -> Self {
+        let mut new = Self::new();
+        new.map = self.map.clone();
+        new.map.retain(|k, _| set.contains(k));
         new
     }
 
-    pub fn search_all<'a, T: 'static>(graphs: &'a [&Self]) -> Option<&'a T> {
-        for graph in graphs {
-            if let Some(ret) = graph.get_node::<T>() {
-                return Some(ret);
+    /// Filters the graph down to `set` and the multi-provider contributions for importing modules.
+    ///
+    /// Exported transient and request-scoped providers inject their dependencies
+    /// from this module's graph and `imports`. In a request, they are built in
+    /// the request's graph, so they share its request-scoped providers.
+    pub fn export(&self, set: HashSet<Token>, imports: &[&Self]) -> Self {
+        let set: HashSet<Token> = set
+            .into_iter()
+            .chain(self.multi.iter().map(|(_, token)| *token))
+            .collect();
+        let mut new = self.filter_by(set.clone());
+        new.multi = self.multi.clone();
+        let layers: Arc<Vec<Self>> = Arc::new(
+            std::iter::once(self)
+                .chain(imports.iter().copied())
+                .cloned()
+                .collect(),
+        );
+        for pending in self.pending.iter().filter(|p| set.contains(&p.token)) {
+            let layers = layers.clone();
+            let factory = pending.factory.clone();
+            new.pending.push(Pending {
+                factory: Arc::new(move |graph, _| {
+                    let imports: Vec<&Self> = layers.iter().collect();
+                    match graph.request {
+                        true => factory(graph, &imports),
+                        // Transient, so nothing it builds is kept by the injecting module.
+                        false => {
+                            Self::new().filling_lazy(&imports, |scratch| factory(scratch, &imports))
+                        }
+                    }
+                }),
+                ..pending.clone()
+            });
+        }
+        new
+    }
+
+    /// Adds the nodes and providers of `other` that this graph does not have yet.
+    pub fn merge(&mut self, other: &Self) {
+        for (token, node) in &other.map {
+            self.map.entry(*token).or_insert_with(|| node.clone());
+        }
+        for pending in &other.pending {
+            if !self.pending.iter().any(|p| p.token == pending.token) {
+                self.pending.push(pending.clone());
+            }
+        }
+        for contribution in &other.multi {
+            if !self.multi.contains(contribution) {
+                self.multi.push(*contribution);
             }
         }
-        None
     }
 
-    pub fn get_node<T: 'static>(&self) -> Option<&T> {
+    /// Replaces the nodes for `tokens` with those in `overrides`, returning the replaced tokens.
+    pub(crate) fn apply_overrides(
+        &mut self,
+        overrides: &Self,
+        tokens: &HashSet<Token>,
+    ) -> Vec<Token> {
+        let mut applied = Vec::new();
+        for (token, node) in overrides.map.iter().filter(|(t, _)| tokens.contains(t)) {
+            self.map.insert(*token, node.clone());
+            applied.push(*token);
+        }
+        applied
+    }
+
+    pub(crate) fn tokens(&self) -> impl Iterator<Item = &Token> {
+        self.map.keys()
+    }
+
+    fn declare_with(&mut self, token: Token, name: &'static str, scope: Scope, factory: Factory) {
+        if self.map.contains_key(&token) || self.pending.iter().any(|p| p.token == token) {
+            return;
+        }
+        self.pending.push(Pending {
+            token,
+            name,
+            scope,
+            factory,
+        });
+    }
+
+    /// Declares `T` so it is constructed the first time it is injected.
+    pub fn declare<T: Injected<Output = T> + 'static>(&mut self, name: &'static str) {
+        self.declare_with(Token::of::<T>(), name, T::SCOPE, Self::constructing::<T>());
+    }
+
+    /// A factory resolving `T`, registering its lifecycle hooks if it is a singleton.
+    fn constructing<T: Injected + 'static>() -> Factory
+    where
+        T::Output: 'static,
+    {
+        Arc::new(|graph, imports| {
+            let value = Arc::new(T::resolve(graph, imports)?);
+            if T::SCOPE == Scope::Singleton {
+                T::register_hooks(&value, &mut graph.hooks);
+            }
+            Ok(value)
+        })
+    }
+
+    /// Declares `Arc<I>` so that injecting it constructs `T` and upcasts it to `I`.
+    pub fn declare_as<I, T>(&mut self, name: &'static str)
+    where
+        I: ?Sized + Interface<T> + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self.declare_with(
+            Token::of::<Arc<I>>(),
+            name,
+            T::SCOPE,
+            Self::upcasting::<I, T>(),
+        );
+    }
+
+    /// Declares `T` as one of the implementations injected as `Multi<I>`.
+    pub fn declare_multi<I, T>(&mut self, name: &'static str)
+    where
+        I: ?Sized + Interface<T> + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        let token = Token::of::<Contribution<I, T>>();
+        self.declare_with(token, name, T::SCOPE, Self::upcasting::<I, T>());
+        if !self.multi.iter().any(|(_, t)| *t == token) {
+            self.multi.push((TypeId::of::<I>(), token));
+        }
+    }
+
+    /// A factory building `T` and upcasting it to `Arc<I>`.
+    fn upcasting<I, T>() -> Factory
+    where
+        I: ?Sized + Interface<T> + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        Arc::new(|graph, imports| {
+            let implementation = match T::SCOPE {
+                Scope::Singleton => graph.resolve::<Arc<T>>(imports)?,
+                _ => Arc::new(T::resolve(graph, imports)?),
+            };
+            Ok(Arc::new(I::upcast(implementation)))
+        })
+    }
+
+    /// Declares `Arc<T>` so that injecting it calls `factory` with its injected dependencies.
+    pub fn declare_factory<T, D, F>(&mut self, name: &'static str, factory: F)
+    where
+        T: Send + Sync + 'static,
+        D: Dependencies,
+        F: Fn(D) -> T + Send + Sync + 'static,
+    {
+        self.declare_with(
+            Token::of::<Arc<T>>(),
+            name,
+            Scope::Singleton,
+            Arc::new(move |graph, imports| {
+                let deps = D::inject(graph, imports).map_err(|err| err.required_by(name))?;
+                Ok(Arc::new(Arc::new(factory(deps))))
+            }),
+        );
+    }
+
+    /// Constructs the declared provider `token`, failing if it is already being constructed.
+    ///
+    /// Singletons, and request-scoped providers in a request graph, are cached;
+    /// transient providers are rebuilt on every call.
+    fn instantiate(&mut self, token: Token, imports: &[&Self]) -> Result<Option<Node>, SeptError> {
+        let found = self
+            .pending
+            .iter()
+            .chain(imports.iter().flat_map(|graph| graph.pending.iter()))
+            .find(|p| p.token == token);
+        let Some(pending) = found.cloned() else {
+            return Ok(None);
+        };
+        if pending.scope == Scope::Request && !self.request {
+            return Err(SeptError::RequestScoped {
+                type_name: pending.name,
+                chain: Vec::new(),
+            });
+        }
+        if let Some(start) = self.resolving.iter().position(|(t, _)| *t == token) {
+            let mut path: Vec<_> = self.resolving[start..].iter().map(|(_, n)| *n).collect();
+            path.push(pending.name);
+            return Err(SeptError::CircularDependency { path });
+        }
+
+        self.resolving.push((token, pending.name));
+        let built = (pending.factory)(self, imports);
+        self.resolving.pop();
+
+        let node = built?;
+        if pending.scope != Scope::Transient {
+            self.pending.retain(|p| p.token != token);
+            self.map.insert(token, node.clone());
+            self.register_provided(token, &node);
+        }
+        Ok(Some(node))
+    }
+
+    fn try_inject_token<T: Clone + 'static>(
+        &mut self,
+        token: Token,
+        imports: &[&Self],
+    ) -> Result<Option<T>, SeptError> {
+        let found = self
+            .node::<T>(token)
+            .or_else(|| imports.iter().find_map(|graph| graph.node::<T>(token)));
+        if let Some(node) = found {
+            return Ok(Some(node.clone()));
+        }
+        Ok(self
+            .instantiate(token, imports)?
+            .as_deref()
+            .and_then(|node| (node as &(dyn Any + Send + 'static)).downcast_ref::<T>())
+            .cloned())
+    }
+
+    fn inject_token<T: Clone + 'static>(
+        &mut self,
+        token: Token,
+        imports: &[&Self],
+    ) -> Result<T, SeptError> {
+        self.try_inject_token(token, imports)?
+            .ok_or_else(|| token.missing::<T>())
+    }
+
+    /// Injects `T` from this graph or the imported graphs, constructing it if it was declared.
+    pub fn inject<T: Clone + 'static>(&mut self, imports: &[&Self]) -> Result<T, SeptError> {
+        self.inject_token(Token::of::<T>(), imports)
+    }
+
+    /// Injects the `T` registered under `name`.
+    pub fn inject_named<T: Clone + 'static>(
+        &mut self,
+        name: &'static str,
+        imports: &[&Self],
+    ) -> Result<T, SeptError> {
+        self.inject_token(Token::named::<T>(name), imports)
+    }
+
+    /// Injects `T` if any graph in scope provides it.
+    ///
+    /// Errors raised while constructing a declared `T` are still returned.
+    pub fn inject_optional<T: Clone + 'static>(
+        &mut self,
+        imports: &[&Self],
+    ) -> Result<Option<T>, SeptError> {
+        self.try_inject_token(Token::of::<T>(), imports)
+    }
+
+    /// Injects the `T` registered under `name` if any graph in scope provides it.
+    pub fn inject_optional_named<T: Clone + 'static>(
+        &mut self,
+        name: &'static str,
+        imports: &[&Self],
+    ) -> Result<Option<T>, SeptError> {
+        self.try_inject_token(Token::named::<T>(name), imports)
+    }
+
+    /// Injects the contribution of `T` to the multi-provider `I`.
+    pub fn inject_contribution<I, T>(&mut self, imports: &[&Self]) -> Result<Arc<I>, SeptError>
+    where
+        I: ?Sized + Send + Sync + 'static,
+        T: 'static,
+    {
+        self.inject_token(Token::of::<Contribution<I, T>>(), imports)
+    }
+
+    /// Injects every contribution to `I` from this graph and the imported graphs.
+    pub fn inject_multi<I: ?Sized + Send + Sync + 'static>(
+        &mut self,
+        imports: &[&Self],
+    ) -> Result<Multi<I>, SeptError> {
+        let mut tokens: Vec<Token> = Vec::new();
+        for (id, token) in self
+            .multi
+            .iter()
+            .chain(imports.iter().flat_map(|graph| graph.multi.iter()))
+        {
+            if *id == TypeId::of::<I>() && !tokens.contains(token) {
+                tokens.push(*token);
+            }
+        }
+        let mut contributions = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            contributions.push(self.inject_token::<Arc<I>>(token, imports)?);
+        }
+        Ok(Multi(contributions))
+    }
+
+    /// Defers injecting `T` until [`Graph::fill_lazy`] runs.
+    pub fn inject_lazy<T: Clone + Send + Sync + 'static>(&mut self) -> Lazy<T> {
+        self.lazy_token(Token::of::<T>())
+    }
+
+    /// Defers injecting the `T` registered under `name` until [`Graph::fill_lazy`] runs.
+    pub fn inject_lazy_named<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        name: &'static str,
+    ) -> Lazy<T> {
+        self.lazy_token(Token::named::<T>(name))
+    }
+
+    fn lazy_token<T: Clone + Send + Sync + 'static>(&mut self, token: Token) -> Lazy<T> {
+        let cell = Arc::new(OnceLock::new());
+        self.lazy.push(Arc::new(Slot {
+            token,
+            cell: cell.clone(),
+        }));
+        Lazy(cell)
+    }
+
+    /// Resolves every `Lazy` handed out by this graph.
+    pub fn fill_lazy(&mut self, imports: &[&Self]) -> Result<(), SeptError> {
+        self.fill_slots(0, imports, |_, _| false).map(drop)
+    }
+
+    /// Resolves the `Lazy`s whose provider is in scope, keeping the others for
+    /// `fill_deferred_lazy`, e.g. the exports of a forward reference.
+    pub(crate) fn fill_lazy_in_scope(&mut self, imports: &[&Self]) -> Result<(), SeptError> {
+        let in_scope = |graph: &Self, token| {
+            std::iter::once(graph)
+                .chain(imports.iter().copied())
+                .any(|graph| graph.provides(token))
+        };
+        self.lazy = self.fill_slots(0, imports, |graph, token| !in_scope(graph, token))?;
+        Ok(())
+    }
+
+    /// Resolves the `Lazy`s kept by `fill_lazy_in_scope`; `imports` must include this graph.
+    pub(crate) fn fill_deferred_lazy(&self, imports: &[&Self]) -> Result<(), SeptError> {
+        Self::new().filling_lazy(imports, |scratch| {
+            self.lazy
+                .iter()
+                .try_for_each(|slot| slot.fill(scratch, imports))
+        })
+    }
+
+    /// Runs `build`, then fills the `Lazy`s handed out while it ran, for providers
+    /// built after their module.
+    pub(crate) fn filling_lazy<R>(
+        &mut self,
+        imports: &[&Self],
+        build: impl FnOnce(&mut Self) -> Result<R, SeptError>,
+    ) -> Result<R, SeptError> {
+        let start = self.lazy.len();
+        let built = build(self)?;
+        self.fill_slots(start, imports, |_, _| false)?;
+        Ok(built)
+    }
+
+    /// Fills the slots from `start` on, including those of the providers the filling
+    /// builds, and returns the ones `defer` skipped.
+    fn fill_slots(
+        &mut self,
+        start: usize,
+        imports: &[&Self],
+        defer: impl Fn(&Self, Token) -> bool,
+    ) -> Result<Vec<Arc<dyn LazySlot>>, SeptError> {
+        let mut deferred = Vec::new();
+        let mut seen: Vec<Token> = Vec::new();
+        let mut rounds = 0;
+        while self.lazy.len() > start {
+            let slots = self.lazy.split_off(start);
+            for token in slots.iter().map(|slot| slot.token()) {
+                if !seen.contains(&token) {
+                    seen.push(token);
+                }
+            }
+            // Each round follows a `Lazy` of the last, so only a cycle of transient
+            // providers can need more rounds than there are distinct tokens.
+            rounds += 1;
+            if rounds > seen.len() {
+                let path = seen.iter().map(Token::type_name).collect();
+                return Err(SeptError::CircularDependency { path });
+            }
+            for slot in slots {
+                match defer(self, slot.token()) {
+                    true => deferred.push(slot),
+                    false => slot.fill(self, imports)?,
+                }
+            }
+        }
+        Ok(deferred)
+    }
+
+    fn provides(&self, token: Token) -> bool {
+        self.map.contains_key(&token) || self.pending.iter().any(|p| p.token == token)
+    }
+
+    pub(crate) fn hooks(&self) -> &Hooks {
+        &self.hooks
+    }
+
+    /// Registers the lifecycle hooks of `Arc<T>` with `register` once it is constructed
+    /// or provided, for factory and value providers.
+    pub(crate) fn on_provided<T: Send + Sync + 'static>(
+        &mut self,
+        register: fn(&Arc<T>, &mut Hooks),
+    ) {
+        let registrar: Registrar = Arc::new(move |node, hooks| {
+            if let Some(value) = (&**node as &(dyn Any + Send + 'static)).downcast_ref() {
+                register(value, hooks);
+            }
+        });
+        self.registrars.push((Token::of::<Arc<T>>(), registrar));
+    }
+
+    fn register_provided(&mut self, token: Token, node: &Node) {
+        for (_, registrar) in self.registrars.iter().filter(|(t, _)| *t == token) {
+            registrar(node, &mut self.hooks);
+        }
+    }
+
+    /// Registers the lifecycle hooks of `value`, which was constructed outside of this graph.
+    pub(crate) fn register_hooks<T: Injected<Output = T>>(&mut self, value: &Arc<T>) {
+        T::register_hooks(value, &mut self.hooks);
+    }
+
+    pub fn search_all<'a, T: 'static>(graphs: &'a [&Self]) -> Option<&'a T> {
+        graphs.iter().find_map(|graph| graph.get_node::<T>())
+    }
+
+    pub fn search_named<'a, T: 'static>(graphs: &'a [&Self], name: &'static str) -> Option<&'a T> {
+        graphs.iter().find_map(|graph| graph.get_named::<T>(name))
+    }
+
+    fn node<T: 'static>(&self, token: Token) -> Option<&T> {
         self.map
-            .get(&TypeId::of::<T>())
+            .get(&token)
             .and_then(|boxed| (&**boxed as &(dyn Any + Send + 'static)).downcast_ref())
     }
 
+    pub fn get_node<T: 'static>(&self) -> Option<&T> {
+        self.node(Token::of::<T>())
+    }
+
+    pub fn get_named<T: 'static>(&self, name: &'static str) -> Option<&T> {
+        self.node(Token::named::<T>(name))
+    }
+
     pub fn get_ptr<T: 'static>(&self) -> Option<Arc<T>> {
-        self.map.get(&TypeId::of::<T>()).and_then(|boxed| {
-            (&**boxed as &(dyn Any + Send + 'static))
-                .downcast_ref::<Arc<T>>()
-                .cloned()
-        })
+        self.node::<Arc<T>>(Token::of::<T>()).cloned()
     }
 
     pub fn contains<T: 'static>(&self) -> bool {
-        self.map.get(&TypeId::of::<T>()).is_some()
+        self.map.contains_key(&Token::of::<T>())
     }
 
     pub fn provide<T: Send + Sync + 'static>(&mut self, t: Arc<T>) -> &T {
-        let exists = self.contains::<T>();
-        if !exists {
-            self.map.insert(TypeId::of::<T>(), t);
+        let token = Token::of::<T>();
+        if !self.map.contains_key(&token) {
+            let node: Node = t;
+            self.register_provided(token, &node);
+            self.map.insert(token, node);
         }
         self.get_node::<T>().unwrap()
     }
 
-    pub fn resolve<'a, T: Injected + Sync + Send + 'static>(
-        &'a mut self,
-        imports: &'a [&Self],
-    ) -> &'a T {
-        let exists = self.contains::<T>();
-        for graph in imports {
-            let exists = graph.contains::<T>();
-            if exists {
-                return graph.get_node::<T>().unwrap();
+    pub fn provide_named<T: Send + Sync + 'static>(&mut self, name: &'static str, t: Arc<T>) -> &T {
+        self.map.entry(Token::named::<T>(name)).or_insert(t);
+        self.get_named::<T>(name).unwrap()
+    }
+
+    /// Injects `T`, declaring it in this graph first if nothing in scope provides it.
+    ///
+    /// Like any declared provider, a transient `T` is built anew on every call.
+    pub fn resolve<T>(&mut self, imports: &[&Self]) -> Result<T, SeptError>
+    where
+        T: Injected<Output = T> + Clone + 'static,
+    {
+        if let Some(node) = Self::search_all(imports) {
+            return Ok(Clone::clone(node));
+        }
+        self.declare_with(
+            Token::of::<T>(),
+            std::any::type_name::<T>(),
+            T::SCOPE,
+            Self::constructing::<T>(),
+        );
+        self.inject::<T>(imports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_module::{ApplicationContext, Module, ModuleFactory};
+    use crate::Injectable;
+
+    fn get_empty_ctx() -> ApplicationContext {
+        ApplicationContext::new(Graph::new())
+    }
+
+    #[derive(Clone, Injectable)]
+    struct CyclicA {
+        _b: Arc<CyclicB>,
+    }
+
+    #[derive(Clone, Injectable)]
+    struct CyclicB {
+        _a: Arc<CyclicA>,
+    }
+
+    #[actix_rt::test]
+    async fn test_circular_dependency_is_reported() {
+        let mut ctx = get_empty_ctx();
+        let result = Module::new()
+            .provide::<CyclicA>()
+            .provide::<CyclicB>()
+            .build(&mut ctx)
+            .await;
+        let err = result.err().expect("expected a circular dependency error");
+        let SeptError::CircularDependency { path } = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(
+            *path,
+            [
+                std::any::type_name::<CyclicA>(),
+                std::any::type_name::<CyclicB>(),
+                std::any::type_name::<CyclicA>(),
+            ]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_lazy_breaks_cycle() {
+        #[derive(Clone, Injectable)]
+        struct LazyA {
+            #[inject(lazy)]
+            b: Lazy<Arc<LazyB>>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct LazyB {
+            _a: Arc<LazyA>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<LazyA>()
+            .provide::<LazyB>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let a = resolved.graph.get_node::<Arc<LazyA>>().unwrap();
+        assert!(a.b.get().is_some());
+    }
+
+    #[actix_rt::test]
+    async fn test_trait_object_resolves_to_bound_implementation() {
+        #[crate::interface]
+        trait Repository: Send + Sync {
+            fn name(&self) -> &'static str;
+        }
+
+        #[derive(Clone, Injectable)]
+        struct InMemoryRepository;
+
+        impl Repository for InMemoryRepository {
+            fn name(&self) -> &'static str {
+                "in-memory"
             }
         }
-        if !exists {
-            let new = T::resolve(self, imports);
-            self.map.insert(TypeId::of::<T>(), Arc::new(new));
+
+        #[derive(Clone, Injectable)]
+        struct UserService {
+            repository: Arc<dyn Repository>,
         }
-        self.get_node::<T>().unwrap()
+
+        #[crate::module]
+        #[exports(dyn Repository)]
+        #[providers(InMemoryRepository as dyn Repository)]
+        struct RepositoryModule;
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<RepositoryModule>()
+            .provide::<UserService>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let service = resolved.graph.get_node::<Arc<UserService>>().unwrap();
+        assert_eq!(service.repository.name(), "in-memory");
+    }
+
+    #[actix_rt::test]
+    async fn test_named_providers_are_distinct() {
+        #[derive(Clone, Injectable)]
+        struct Databases {
+            #[inject("primary")]
+            primary: String,
+            #[inject("analytics")]
+            analytics: String,
+        }
+
+        struct DatabaseModule;
+        impl ModuleFactory for DatabaseModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .export_named::<String>("analytics")
+                    .provide_named("analytics", "postgres://analytics".to_string())
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<DatabaseModule>()
+            .provide_named("primary", "postgres://primary".to_string())
+            .provide::<Databases>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let databases = resolved.graph.get_node::<Arc<Databases>>().unwrap();
+        assert_eq!(databases.primary, "postgres://primary");
+        assert_eq!(databases.analytics, "postgres://analytics");
+    }
+
+    #[actix_rt::test]
+    async fn test_factory_provider_receives_dependencies() {
+        #[derive(Clone)]
+        struct DbConfig {
+            url: &'static str,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Tracer;
+
+        struct Pool {
+            url: &'static str,
+        }
+
+        fn create_pool((config, _tracer): (Value<DbConfig>, Arc<Tracer>)) -> Pool {
+            Pool { url: config.url }
+        }
+
+        #[crate::module]
+        #[exports(Pool)]
+        #[providers(factory(create_pool), Tracer)]
+        struct DbModule;
+
+        let mut ctx = get_empty_ctx();
+        ctx.global_providers.provide(Arc::new(Value(DbConfig {
+            url: "postgres://localhost",
+        })));
+        let resolved = Module::new()
+            .import::<DbModule>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let pool = resolved.imports[0].graph.get_node::<Arc<Pool>>().unwrap();
+        assert_eq!(pool.url, "postgres://localhost");
+    }
+
+    #[actix_rt::test]
+    async fn test_async_factory_provider_is_awaited() {
+        struct Pool {
+            size: usize,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Repository {
+            pool: Arc<Pool>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Repository>()
+            .provide_async_factory(|(size,): (usize,)| async move {
+                actix_rt::task::yield_now().await;
+                Ok::<_, std::io::Error>(Pool { size })
+            })
+            .provide_val(4_usize)
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let repository = resolved.graph.get_node::<Arc<Repository>>().unwrap();
+        assert_eq!(repository.pool.size, 4);
+    }
+
+    #[actix_rt::test]
+    async fn test_optional_dependencies() {
+        #[derive(Clone, Injectable)]
+        struct Metrics;
+
+        #[derive(Clone, Injectable)]
+        struct Registry;
+
+        #[derive(Clone, Injectable)]
+        struct Service {
+            metrics: Option<Arc<Metrics>>,
+            #[inject(optional)]
+            registry: Option<Arc<Registry>>,
+            #[inject("region", optional)]
+            region: Option<Arc<String>>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Metrics>()
+            .provide::<Service>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let service = resolved.graph.get_node::<Arc<Service>>().unwrap();
+        assert!(service.metrics.is_some());
+        assert!(service.registry.is_none());
+        assert!(service.region.is_none());
+    }
+
+    #[crate::interface]
+    trait HealthIndicator: Send + Sync {
+        fn name(&self) -> &'static str;
+    }
+
+    #[derive(Clone, Injectable)]
+    struct DatabaseHealth;
+
+    impl HealthIndicator for DatabaseHealth {
+        fn name(&self) -> &'static str {
+            "database"
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct CacheHealth;
+
+    impl HealthIndicator for CacheHealth {
+        fn name(&self) -> &'static str {
+            "cache"
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct HealthCheck {
+        indicators: Multi<dyn HealthIndicator>,
+        all: Vec<Arc<dyn HealthIndicator>>,
+    }
+
+    struct CacheModule;
+
+    impl ModuleFactory for CacheModule {
+        fn get_module() -> Module {
+            Module::new().provide_multi::<dyn HealthIndicator, CacheHealth>()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_multi_providers_gather_imported_contributions() {
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<CacheModule>()
+            .provide_multi::<dyn HealthIndicator, DatabaseHealth>()
+            .provide::<HealthCheck>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let health = resolved.graph.get_node::<Arc<HealthCheck>>().unwrap();
+        let names: Vec<_> = health.indicators.iter().map(|i| i.name()).collect();
+        assert_eq!(names, ["database", "cache"]);
+        assert_eq!(health.all.len(), 2);
+    }
+
+    #[actix_rt::test]
+    async fn test_vec_of_concrete_providers_is_not_a_multi_provider() {
+        #[derive(Clone, Injectable)]
+        struct Snapshot {
+            _health: Vec<Arc<DatabaseHealth>>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let built = Module::new()
+            .provide_multi::<dyn HealthIndicator, DatabaseHealth>()
+            .provide::<Snapshot>()
+            .build(&mut ctx)
+            .await;
+        let err = built.err().expect("expected a missing provider error");
+        assert!(matches!(err, SeptError::MissingProvider { .. }), "{}", err);
     }
 }
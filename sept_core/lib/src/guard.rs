@@ -0,0 +1,262 @@
+use crate::error::SeptError;
+use crate::exception::Forbidden;
+use crate::graph::Injected;
+use crate::sept_module::ModuleRef;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::HttpRequest;
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The request being served and the handler it was routed to.
+pub struct ExecutionContext {
+    request: HttpRequest,
+    handler: &'static str,
+}
+
+impl ExecutionContext {
+    pub(crate) fn new(request: HttpRequest, handler: &'static str) -> Self {
+        Self { request, handler }
+    }
+
+    pub fn request(&self) -> &HttpRequest {
+        &self.request
+    }
+
+    /// The handler the request was routed to, e.g. `UsersClient::list`.
+    pub fn handler(&self) -> &'static str {
+        self.handler
+    }
+}
+
+/// Decides whether a request may reach its handler.
+///
+/// Guards are attached with `#[guard(AuthGuard)]` on a handler, a `#[client]`
+/// impl or a `#[module]`, and run in that order from the module down. Returning
+/// `Ok(false)` rejects the request with 403 Forbidden; return e.g.
+/// `exception::Unauthorized` to reject it with 401.
+///
+/// Guards, interceptors, pipes and filters are injected from the module serving
+/// the route, or built from its providers if it provides none. Each is resolved
+/// once when that module is built, which fails if it cannot be, and shared by
+/// all of the module's routes.
+#[allow(async_fn_in_trait)]
+pub trait Guard {
+    async fn can_activate(&self, context: &ExecutionContext) -> Result<bool, SeptError>;
+}
+
+/// A resolved guard, see [`resolve`].
+pub type GuardFn = Arc<
+    dyn Fn(Rc<ExecutionContext>) -> LocalBoxFuture<'static, Result<bool, SeptError>> + Send + Sync,
+>;
+
+/// Resolves a guard from a module, see [`resolve`].
+pub type GuardFactory = fn(&mut ModuleRef) -> Result<GuardFn, SeptError>;
+
+/// Injects the guard `G` from `module`, or builds it from the module's providers.
+pub fn resolve<G>(module: &mut ModuleRef) -> Result<GuardFn, SeptError>
+where
+    G: Guard + Injected<Output = G> + Send + Sync + 'static,
+{
+    module.resolve_enhancer::<G>()?;
+    Ok(get::<G>(module))
+}
+
+/// The guard `G` resolved when `module` was built, for the routes of its clients.
+pub fn get<G>(module: &ModuleRef) -> GuardFn
+where
+    G: Guard + Send + Sync + 'static,
+{
+    let guard = module.enhancer::<G>();
+    Arc::new(move |context| {
+        let guard = guard.clone();
+        Box::pin(async move { guard.can_activate(&context).await })
+    })
+}
+
+/// Middleware running the guards of a route before its handler, generated by `#[client]`.
+pub struct Guards {
+    handler: &'static str,
+    guards: Rc<Vec<GuardFn>>,
+}
+
+impl Guards {
+    /// Runs the guards of `module`, then `guards`, for the route served by `handler`.
+    pub fn new(module: &ModuleRef, handler: &'static str, guards: Vec<GuardFn>) -> Self {
+        let guards = module.guards().iter().cloned().chain(guards).collect();
+        Self {
+            handler,
+            guards: Rc::new(guards),
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for Guards
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = GuardsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(GuardsMiddleware {
+            service: Rc::new(service),
+            handler: self.handler,
+            guards: self.guards.clone(),
+        }))
+    }
+}
+
+pub struct GuardsMiddleware<S> {
+    service: Rc<S>,
+    handler: &'static str,
+    guards: Rc<Vec<GuardFn>>,
+}
+
+impl<S, B> Service<ServiceRequest> for GuardsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let guards = self.guards.clone();
+        let handler = self.handler;
+        Box::pin(async move {
+            let context = Rc::new(ExecutionContext::new(req.request().clone(), handler));
+            for guard in guards.iter() {
+                if !guard(context.clone())
+                    .await
+                    .map_err(SeptError::into_error)?
+                {
+                    return Err(Forbidden::new("Forbidden resource").into());
+                }
+            }
+            drop(context);
+            service.call(req).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::graph::Graph;
+    use crate::sept_application::SeptApplication;
+    use crate::testing::TestApp;
+    use crate::Injectable;
+
+    #[derive(Clone, Injectable)]
+    struct ApiKeys;
+
+    #[derive(Clone, Injectable)]
+    struct ApiKeyGuard {
+        _keys: Arc<ApiKeys>,
+    }
+
+    impl Guard for ApiKeyGuard {
+        async fn can_activate(&self, context: &ExecutionContext) -> Result<bool, SeptError> {
+            match context.request().headers().get("x-api-key") {
+                Some(key) => Ok(key == "secret"),
+                None => Err(crate::exception::Unauthorized::new("missing api key").into()),
+            }
+        }
+    }
+
+    static TENANT_GUARDS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    struct TenantGuard;
+
+    impl Injected for TenantGuard {
+        type Output = Self;
+
+        fn resolve(_: &mut Graph, _: &[&Graph]) -> Result<Self, SeptError> {
+            TENANT_GUARDS.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Self)
+        }
+    }
+
+    impl Guard for TenantGuard {
+        async fn can_activate(&self, context: &ExecutionContext) -> Result<bool, SeptError> {
+            Ok(context.request().headers().contains_key("x-tenant"))
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct AdminGuard;
+
+    impl Guard for AdminGuard {
+        async fn can_activate(&self, context: &ExecutionContext) -> Result<bool, SeptError> {
+            assert_eq!(context.handler(), "ReportsClient::purge");
+            Ok(context.request().headers().contains_key("x-admin"))
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ReportsClient;
+
+    #[crate::client("/reports")]
+    #[guard(TenantGuard)]
+    impl ReportsClient {
+        #[get("")]
+        async fn list(_client: Self) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::Ok().finish()
+        }
+
+        #[delete("")]
+        #[guard(AdminGuard)]
+        async fn purge(_client: Self) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::NoContent().finish()
+        }
+    }
+
+    #[crate::module]
+    #[providers(ApiKeys)]
+    #[clients(ReportsClient)]
+    #[guard(ApiKeyGuard)]
+    struct ReportsModule;
+
+    #[actix_rt::test]
+    async fn test_guards_run_from_module_to_handler() {
+        use actix_web::http::Method;
+
+        let app = TestApp::create::<ReportsModule>(SeptApplication::default())
+            .await
+            .unwrap();
+        let send = |method, headers: &[&'static str]| {
+            let mut request = app.request(method, "/reports");
+            for &header in headers {
+                request = request.header((header, "secret"));
+            }
+            request.send()
+        };
+        send(Method::GET, &["x-tenant"]).await.assert_status(401);
+        send(Method::GET, &["x-api-key"]).await.assert_status(403);
+        send(Method::GET, &["x-api-key", "x-tenant"])
+            .await
+            .assert_status(200);
+        send(Method::DELETE, &["x-api-key", "x-tenant"])
+            .await
+            .assert_status(403);
+        send(Method::DELETE, &["x-api-key", "x-tenant", "x-admin"])
+            .await
+            .assert_status(204);
+        assert_eq!(
+            app.context().routes()[1].guards,
+            ["TenantGuard", "AdminGuard"]
+        );
+        // Built once when the module was, though both routes run it.
+        assert_eq!(TENANT_GUARDS.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+}
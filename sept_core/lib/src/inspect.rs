@@ -0,0 +1,299 @@
+use crate::graph::Token;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// What a built module provides, exports and imports, by type name.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ModuleInfo {
+    /// The module's type name, followed by its key for dynamic modules built more than once.
+    pub name: String,
+    pub global: bool,
+    pub imports: Vec<String>,
+    /// Imports resolved after the module was built, see `Module::forward_ref`.
+    pub forward_refs: Vec<String>,
+    pub providers: Vec<String>,
+    pub exports: Vec<String>,
+    pub clients: Vec<String>,
+    /// The routes of `clients`, in declaration order.
+    pub routes: Vec<RouteInfo>,
+}
+
+/// A route served by a client, as declared with `#[client]` and its method attributes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RouteInfo {
+    pub method: &'static str,
+    /// The full path, the client's scope included.
+    pub path: &'static str,
+    /// The handler, e.g. `UsersClient::list`.
+    pub handler: &'static str,
+    /// The middleware wrapping the route, the client's first.
+    pub wrappers: Vec<&'static str>,
+    /// The `method(...)` guards of the route, then its `#[guard]` types, each the client's first.
+    pub guards: Vec<&'static str>,
+}
+
+impl ModuleInfo {
+    pub(crate) fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            ..Self::default()
+        }
+    }
+}
+
+/// The module graph of an application, with its modules in dependency order.
+///
+/// Obtained from `SeptContext::graph`, it renders as Graphviz DOT, Mermaid or JSON.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ModuleGraph {
+    pub modules: Vec<ModuleInfo>,
+}
+
+impl ModuleGraph {
+    pub fn module(&self, name: &str) -> Option<&ModuleInfo> {
+        self.modules.iter().find(|module| module.name == name)
+    }
+
+    /// Renders the graph in Graphviz DOT, one box per module listing what it provides.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph modules {\n    node [shape=box];\n");
+        for module in &self.modules {
+            let mut label = short_name(&module.name);
+            for (section, names) in sections(module) {
+                let _ = write!(label, "\\n{}: {}", section, names.join(", "));
+            }
+            let global = if module.global { ", peripheries=2" } else { "" };
+            let _ = writeln!(
+                dot,
+                "    {} [label={}{}];",
+                quoted(&module.name),
+                quoted(&label),
+                global
+            );
+        }
+        for module in &self.modules {
+            for import in &module.imports {
+                let _ = writeln!(dot, "    {} -> {};", quoted(&module.name), quoted(import));
+            }
+            for import in &module.forward_refs {
+                let _ = writeln!(
+                    dot,
+                    "    {} -> {} [style=dashed];",
+                    quoted(&module.name),
+                    quoted(import)
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the graph as a Mermaid flowchart, forward references as dotted edges.
+    pub fn to_mermaid(&self) -> String {
+        let id = |name: &str| self.modules.iter().position(|module| module.name == name);
+        let mut mermaid = String::from("graph TD\n");
+        for (index, module) in self.modules.iter().enumerate() {
+            let mut label = escape_mermaid(&short_name(&module.name));
+            for (section, names) in sections(module) {
+                let _ = write!(
+                    label,
+                    "<br/>{}: {}",
+                    section,
+                    escape_mermaid(&names.join(", "))
+                );
+            }
+            let (open, close) = if module.global {
+                ("[[", "]]")
+            } else {
+                ("[", "]")
+            };
+            let _ = writeln!(mermaid, "    m{}{}\"{}\"{}", index, open, label, close);
+        }
+        for (index, module) in self.modules.iter().enumerate() {
+            for import in module.imports.iter().filter_map(|name| id(name)) {
+                let _ = writeln!(mermaid, "    m{} --> m{}", index, import);
+            }
+            for import in module.forward_refs.iter().filter_map(|name| id(name)) {
+                let _ = writeln!(mermaid, "    m{} -.-> m{}", index, import);
+            }
+        }
+        mermaid
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!(self)
+    }
+}
+
+/// The non-empty lists of a module, with type names shortened for diagrams.
+fn sections(module: &ModuleInfo) -> Vec<(&'static str, Vec<String>)> {
+    [
+        ("providers", &module.providers),
+        ("exports", &module.exports),
+        ("clients", &module.clients),
+    ]
+    .into_iter()
+    .filter(|(_, names)| !names.is_empty())
+    .map(|(section, names)| (section, names.iter().map(|n| short_name(n)).collect()))
+    .collect()
+}
+
+/// The provided type of `token`, without the `Arc` singletons are stored in, and its name if any.
+pub(crate) fn provided(token: &Token) -> String {
+    let type_name = token.type_name();
+    let type_name = type_name
+        .strip_prefix("alloc::sync::Arc<")
+        .and_then(|inner| inner.strip_suffix('>'))
+        .unwrap_or(type_name);
+    match token.name() {
+        Some(name) => format!("{} \"{}\"", type_name, name),
+        None => type_name.to_owned(),
+    }
+}
+
+/// Strips the module paths of a type name, e.g. `sept::graph::Multi<dyn app::Plugin>` to `Multi<dyn Plugin>`.
+fn short_name(type_name: &str) -> String {
+    let mut short = String::with_capacity(type_name.len());
+    let mut segment = 0;
+    let mut chars = type_name.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == ':' && chars.peek() == Some(&':') {
+            chars.next();
+            short.truncate(segment);
+        } else {
+            short.push(c);
+            if !(c.is_alphanumeric() || c == '_') {
+                segment = short.len();
+            }
+        }
+    }
+    short
+}
+
+fn quoted(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\\\""))
+}
+
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_application::SeptApplication;
+    use crate::sept_module::{Module, ModuleFactory};
+    use crate::Injectable;
+    use std::sync::Arc;
+
+    #[derive(Clone, Injectable)]
+    struct AppConfig;
+
+    struct ConfigModule;
+
+    impl ModuleFactory for ConfigModule {
+        fn get_module() -> Module {
+            Module::new()
+                .global()
+                .provide::<AppConfig>()
+                .export::<AppConfig>()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct UsersClient {
+        _config: Arc<AppConfig>,
+    }
+
+    #[crate::client("/users")]
+    impl UsersClient {
+        #[get("")]
+        async fn list(_client: Self) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::Ok().json(serde_json::json!([{ "name": "ada" }]))
+        }
+
+        #[delete("/{id}", method(is_admin))]
+        async fn remove(_client: Self) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::NoContent().finish()
+        }
+    }
+
+    fn is_admin(ctx: &actix_web::guard::GuardContext) -> bool {
+        ctx.head().headers().contains_key("x-admin")
+    }
+
+    #[crate::module]
+    #[imports(ConfigModule)]
+    #[clients(UsersClient)]
+    struct UsersHttpModule;
+
+    #[actix_rt::test]
+    async fn test_context_graph_describes_modules() {
+        use std::any::type_name;
+
+        let context = SeptApplication::default()
+            .create_context::<UsersHttpModule>()
+            .await
+            .unwrap();
+        let graph = context.graph();
+        let config = graph.module(type_name::<ConfigModule>()).unwrap();
+        assert!(config.global);
+        assert_eq!(config.providers, [type_name::<AppConfig>()]);
+        assert_eq!(config.exports, config.providers);
+        let users = graph.module(type_name::<UsersHttpModule>()).unwrap();
+        assert_eq!(users.imports, [type_name::<ConfigModule>()]);
+        assert_eq!(users.clients, [type_name::<UsersClient>()]);
+        assert!(users.providers.is_empty());
+
+        assert!(graph.to_dot().contains(&format!(
+            "\"{}\" -> \"{}\";",
+            type_name::<UsersHttpModule>(),
+            type_name::<ConfigModule>()
+        )));
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid
+            .contains("m0[[\"ConfigModule<br/>providers: AppConfig<br/>exports: AppConfig\"]]"));
+        assert!(mermaid.contains("m1 --> m0"));
+        assert_eq!(
+            graph.to_json()["modules"][1]["clients"][0],
+            type_name::<UsersClient>()
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_context_lists_client_routes() {
+        use crate::testing::TestApp;
+
+        let app = TestApp::create::<UsersHttpModule>(SeptApplication::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            app.context().routes(),
+            [
+                RouteInfo {
+                    method: "GET",
+                    path: "/users",
+                    handler: "UsersClient::list",
+                    wrappers: vec![],
+                    guards: vec![],
+                },
+                RouteInfo {
+                    method: "DELETE",
+                    path: "/users/{id}",
+                    handler: "UsersClient::remove",
+                    wrappers: vec![],
+                    guards: vec!["is_admin"],
+                },
+            ]
+        );
+        app.delete("/users/1").send().await.assert_status(404);
+        app.delete("/users/1")
+            .header(("x-admin", "1"))
+            .send()
+            .await
+            .assert_status(204);
+    }
+}
@@ -1,7 +1,8 @@
+use crate::error::SeptError;
 use crate::graph::Graph;
 
 use super::{InstrumentationProvider, LogLevel};
-use futures_util::future::{ok, Ready};
+use futures_util::future::{err, ok, Ready};
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -56,8 +57,8 @@ impl Logger {
 
 impl crate::graph::Injected for Logger {
     type Output = Self;
-    fn resolve(_: &mut crate::graph::Graph, _: &[&Graph]) -> Self {
-        panic!("Logger not configured in application!")
+    fn resolve(_: &mut crate::graph::Graph, _: &[&Graph]) -> Result<Self, SeptError> {
+        Err(SeptError::missing::<Self>())
     }
 }
 
@@ -69,7 +70,9 @@ impl actix_web::FromRequest for Logger {
     fn from_request(req: &actix_web::HttpRequest, _: &mut actix_http::Payload) -> Self::Future {
         match req.app_data::<actix_web::web::Data<Self>>() {
             Some(s) => ok(s.get_ref().clone()),
-            None => panic!("Logger not configured in application!"),
+            None => err(actix_web::error::ErrorInternalServerError(
+                "Logger not configured in application!",
+            )),
         }
     }
 }
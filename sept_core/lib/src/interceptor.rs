@@ -0,0 +1,294 @@
+use crate::error::SeptError;
+use crate::graph::Injected;
+use crate::guard::ExecutionContext;
+use crate::sept_module::ModuleRef;
+use actix_web::{HttpRequest, HttpResponse, Responder};
+use futures_util::future::LocalBoxFuture;
+use std::any::Any;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// The result of a handler before it is turned into a response.
+pub struct Handled {
+    value: Box<dyn Any>,
+    respond: fn(Box<dyn Any>, &HttpRequest) -> HttpResponse,
+}
+
+impl Handled {
+    pub fn new<R: Responder + 'static>(value: R) -> Self {
+        Self {
+            value: Box::new(value),
+            respond: respond::<R>,
+        }
+    }
+
+    /// Whether the handler returned an `R`.
+    pub fn is<R: 'static>(&self) -> bool {
+        self.value.is::<R>()
+    }
+
+    pub fn downcast_ref<R: 'static>(&self) -> Option<&R> {
+        self.value.downcast_ref()
+    }
+
+    /// Returns the `R` the handler returned, or `self` if it returned something else.
+    pub fn downcast<R: 'static>(self) -> Result<R, Self> {
+        match self.value.downcast() {
+            Ok(value) => Ok(*value),
+            Err(value) => Err(Self {
+                value,
+                respond: self.respond,
+            }),
+        }
+    }
+
+    /// Replaces the `R` the handler returned with `f(R)`, leaving any other result as is.
+    pub fn map<R, U>(self, f: impl FnOnce(R) -> U) -> Self
+    where
+        R: 'static,
+        U: Responder + 'static,
+    {
+        match self.downcast::<R>() {
+            Ok(value) => Self::new(f(value)),
+            Err(handled) => handled,
+        }
+    }
+
+    pub fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        (self.respond)(self.value, req)
+    }
+}
+
+fn respond<R: Responder + 'static>(value: Box<dyn Any>, req: &HttpRequest) -> HttpResponse {
+    let value = value
+        .downcast::<R>()
+        .expect("`Handled` holds the type it was created with");
+    value.respond_to(req).map_into_boxed_body()
+}
+
+/// Runs around a handler, e.g. to time, cache or transform its result.
+///
+/// Interceptors are attached with `#[interceptor(Timing)]` on a handler, a
+/// `#[client]` impl or a `#[module]`, or to every route with
+/// `SeptApplication::use_global_interceptor`, and wrap one another in that
+/// order from the application down. Not calling [`Next::handle`] skips the
+/// handler and the remaining interceptors. They are resolved like
+/// [`Guard`](crate::guard::Guard)s.
+#[allow(async_fn_in_trait)]
+pub trait Interceptor {
+    async fn intercept(&self, context: &ExecutionContext, next: Next)
+        -> Result<Handled, SeptError>;
+}
+
+/// A resolved interceptor, see [`resolve`].
+pub type InterceptorFn = Arc<
+    dyn Fn(Rc<ExecutionContext>, Next) -> LocalBoxFuture<'static, Result<Handled, SeptError>>
+        + Send
+        + Sync,
+>;
+
+/// Resolves an interceptor from a module, see [`resolve`].
+pub type InterceptorFactory = fn(&mut ModuleRef) -> Result<InterceptorFn, SeptError>;
+
+/// Injects the interceptor `I` from `module`, or builds it from the module's providers.
+pub fn resolve<I>(module: &mut ModuleRef) -> Result<InterceptorFn, SeptError>
+where
+    I: Interceptor + Injected<Output = I> + Send + Sync + 'static,
+{
+    module.resolve_enhancer::<I>()?;
+    Ok(get::<I>(module))
+}
+
+/// The interceptor `I` resolved when `module` was built, for the routes of its clients.
+pub fn get<I>(module: &ModuleRef) -> InterceptorFn
+where
+    I: Interceptor + Send + Sync + 'static,
+{
+    let interceptor = module.enhancer::<I>();
+    Arc::new(move |context, next| {
+        let interceptor = interceptor.clone();
+        Box::pin(async move { interceptor.intercept(&context, next).await })
+    })
+}
+
+type Handler = Box<dyn FnOnce() -> LocalBoxFuture<'static, Result<Handled, SeptError>>>;
+
+/// The remaining interceptors and the handler of a call.
+pub struct Next {
+    interceptors: Rc<Vec<InterceptorFn>>,
+    index: usize,
+    context: Rc<ExecutionContext>,
+    handler: Handler,
+}
+
+impl Next {
+    /// Runs the remaining interceptors and the handler.
+    pub async fn handle(mut self) -> Result<Handled, SeptError> {
+        match self.interceptors.get(self.index).cloned() {
+            Some(interceptor) => {
+                self.index += 1;
+                interceptor(self.context.clone(), self).await
+            }
+            None => (self.handler)().await,
+        }
+    }
+}
+
+/// The interceptors of a route, generated by `#[client]`.
+#[derive(Clone)]
+pub struct Interceptors {
+    handler: &'static str,
+    interceptors: Rc<Vec<InterceptorFn>>,
+}
+
+impl Interceptors {
+    /// Runs the interceptors of `module`, then `interceptors`, for the route served by `handler`.
+    pub fn new(
+        module: &ModuleRef,
+        handler: &'static str,
+        interceptors: Vec<InterceptorFn>,
+    ) -> Self {
+        let interceptors = module
+            .interceptors()
+            .iter()
+            .cloned()
+            .chain(interceptors)
+            .collect();
+        Self {
+            handler,
+            interceptors: Rc::new(interceptors),
+        }
+    }
+
+    /// Calls `handler`, which runs the pipes of the route, through the interceptors and responds.
+    pub async fn intercept<F>(
+        &self,
+        request: HttpRequest,
+        handler: F,
+    ) -> actix_web::Result<HttpResponse>
+    where
+        F: FnOnce() -> LocalBoxFuture<'static, Result<Handled, SeptError>> + 'static,
+    {
+        let next = Next {
+            interceptors: self.interceptors.clone(),
+            index: 0,
+            context: Rc::new(ExecutionContext::new(request.clone(), self.handler)),
+            handler: Box::new(handler),
+        };
+        let handled = next.handle().await.map_err(SeptError::into_error)?;
+        Ok(handled.respond_to(&request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_application::SeptApplication;
+    use crate::testing::TestApp;
+    use crate::Injectable;
+
+    static INTERCEPTED: std::sync::Mutex<Vec<&str>> = std::sync::Mutex::new(Vec::new());
+
+    #[derive(Clone, Injectable)]
+    struct Clock;
+
+    #[derive(Clone, Injectable)]
+    struct Timing {
+        _clock: Arc<Clock>,
+    }
+
+    impl Interceptor for Timing {
+        async fn intercept(&self, _: &ExecutionContext, next: Next) -> Result<Handled, SeptError> {
+            INTERCEPTED.lock().unwrap().push("timing");
+            let started = std::time::Instant::now();
+            let handled = next.handle().await;
+            assert!(started.elapsed() < std::time::Duration::from_secs(1));
+            handled
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Envelope;
+
+    impl Interceptor for Envelope {
+        async fn intercept(&self, _: &ExecutionContext, next: Next) -> Result<Handled, SeptError> {
+            use actix_web::web::Json;
+
+            let handled = next.handle().await?;
+            Ok(handled.map(|Json(data): Json<serde_json::Value>| {
+                Json(serde_json::json!({ "data": data }))
+            }))
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Cache;
+
+    impl Interceptor for Cache {
+        async fn intercept(
+            &self,
+            context: &ExecutionContext,
+            next: Next,
+        ) -> Result<Handled, SeptError> {
+            if context.request().headers().contains_key("x-cached") {
+                let cached = actix_web::HttpResponse::Ok().body("cached");
+                return Ok(Handled::new(cached));
+            }
+            next.handle().await
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Audit;
+
+    impl Interceptor for Audit {
+        async fn intercept(
+            &self,
+            context: &ExecutionContext,
+            next: Next,
+        ) -> Result<Handled, SeptError> {
+            assert_eq!(context.handler(), "ItemsClient::show");
+            INTERCEPTED.lock().unwrap().push("audit");
+            next.handle().await
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ItemsClient;
+
+    #[crate::client("/items")]
+    #[interceptor(Cache)]
+    impl ItemsClient {
+        #[get("/{id}")]
+        #[interceptor(Audit)]
+        async fn show(
+            _client: Self,
+            id: actix_web::web::Path<u32>,
+        ) -> actix_web::web::Json<serde_json::Value> {
+            actix_web::web::Json(serde_json::json!({ "id": id.into_inner() }))
+        }
+    }
+
+    #[crate::module]
+    #[providers(Clock)]
+    #[clients(ItemsClient)]
+    #[interceptor(Envelope)]
+    struct ItemsModule;
+
+    #[actix_rt::test]
+    async fn test_interceptors_wrap_handlers_from_application_down() {
+        let application = SeptApplication::default().use_global_interceptor::<Timing>();
+        let app = TestApp::create::<ItemsModule>(application).await.unwrap();
+        let item: serde_json::Value = app.get("/items/7").send().await.assert_status(200).json();
+        assert_eq!(item, serde_json::json!({ "data": { "id": 7 } }));
+        let cached = app
+            .get("/items/7")
+            .header(("x-cached", "1"))
+            .send()
+            .await
+            .assert_status(200);
+        assert_eq!(cached.text(), "cached");
+        assert_eq!(*INTERCEPTED.lock().unwrap(), ["timing", "audit", "timing"]);
+    }
+}
@@ -1,8 +1,18 @@
 #![warn(clippy::use_self)]
 
+pub mod error;
+pub mod exception;
+pub mod filter;
+pub mod guard;
+pub mod inspect;
 pub mod instrumentation;
+pub mod interceptor;
+pub mod lifecycle;
+pub mod pipe;
 pub mod sept_application;
 pub mod sept_module;
+pub mod shutdown;
+pub mod testing;
 pub use sept_codegen::*;
 #[doc(hidden)]
 pub mod graph;
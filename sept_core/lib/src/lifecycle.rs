@@ -0,0 +1,299 @@
+use crate::error::SeptError;
+use crate::sept_module::ResolvedModule;
+use futures_util::future::LocalBoxFuture;
+use std::future::Future;
+use std::sync::Arc;
+
+/// The error type lifecycle hooks may fail with.
+pub type HookError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Called once every module of the application has been built.
+///
+/// Providers opt in with `#[injectable(hooks(OnModuleInit))]`.
+#[allow(async_fn_in_trait)]
+pub trait OnModuleInit {
+    async fn on_module_init(&self) -> Result<(), HookError>;
+}
+
+/// Called after every `OnModuleInit` hook, before the server starts listening.
+#[allow(async_fn_in_trait)]
+pub trait OnApplicationBootstrap {
+    async fn on_application_bootstrap(&self) -> Result<(), HookError>;
+}
+
+/// Called when the application starts shutting down, while requests are still being served.
+#[allow(async_fn_in_trait)]
+pub trait BeforeApplicationShutdown {
+    async fn before_application_shutdown(&self) -> Result<(), HookError>;
+}
+
+/// Called once in-flight requests have drained, e.g. to flush buffers and close pools.
+#[allow(async_fn_in_trait)]
+pub trait OnApplicationShutdown {
+    async fn on_application_shutdown(&self) -> Result<(), HookError>;
+}
+
+type Hook = Arc<dyn Fn() -> LocalBoxFuture<'static, Result<(), SeptError>> + Send + Sync>;
+
+/// Wraps a hook call so its error names the hook and the provider it failed in.
+fn hook<T, F, Fut>(hook: &'static str, value: Arc<T>, call: F) -> Hook
+where
+    T: Send + Sync + 'static,
+    F: Fn(Arc<T>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), HookError>> + 'static,
+{
+    Arc::new(move || {
+        let called = call(value.clone());
+        Box::pin(async move {
+            called.await.map_err(|source| SeptError::Lifecycle {
+                hook,
+                type_name: std::any::type_name::<T>(),
+                source,
+            })
+        })
+    })
+}
+
+/// The lifecycle hooks of a module's singletons, in construction order.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    module_init: Vec<Hook>,
+    application_bootstrap: Vec<Hook>,
+    before_application_shutdown: Vec<Hook>,
+    application_shutdown: Vec<Hook>,
+}
+
+impl Hooks {
+    pub fn on_module_init<T: OnModuleInit + Send + Sync + 'static>(&mut self, value: Arc<T>) {
+        self.module_init
+            .push(hook("on_module_init", value, |value| async move {
+                value.on_module_init().await
+            }));
+    }
+
+    pub fn on_application_bootstrap<T>(&mut self, value: Arc<T>)
+    where
+        T: OnApplicationBootstrap + Send + Sync + 'static,
+    {
+        self.application_bootstrap.push(hook(
+            "on_application_bootstrap",
+            value,
+            |value| async move { value.on_application_bootstrap().await },
+        ));
+    }
+
+    pub fn before_application_shutdown<T>(&mut self, value: Arc<T>)
+    where
+        T: BeforeApplicationShutdown + Send + Sync + 'static,
+    {
+        self.before_application_shutdown.push(hook(
+            "before_application_shutdown",
+            value,
+            |value| async move { value.before_application_shutdown().await },
+        ));
+    }
+
+    pub fn on_application_shutdown<T>(&mut self, value: Arc<T>)
+    where
+        T: OnApplicationShutdown + Send + Sync + 'static,
+    {
+        self.application_shutdown.push(hook(
+            "on_application_shutdown",
+            value,
+            |value| async move { value.on_application_shutdown().await },
+        ));
+    }
+}
+
+async fn run<'a>(hooks: impl Iterator<Item = &'a Hook>) -> Result<(), SeptError> {
+    for hook in hooks {
+        hook().await?;
+    }
+    Ok(())
+}
+
+/// Runs the startup hooks of `modules`, which are in dependency order.
+pub(crate) async fn bootstrap(modules: &[Arc<ResolvedModule>]) -> Result<(), SeptError> {
+    run(modules.iter().flat_map(|m| &m.graph.hooks().module_init)).await?;
+    run(modules
+        .iter()
+        .flat_map(|m| &m.graph.hooks().application_bootstrap))
+    .await
+}
+
+/// Runs every hook even if an earlier one fails, returning the first error.
+async fn run_all<'a>(hooks: impl Iterator<Item = &'a Hook>) -> Result<(), SeptError> {
+    let mut result = Ok(());
+    for hook in hooks {
+        if let Err(err) = hook().await {
+            tracing::error!("{}", err);
+            result = result.and(Err(err));
+        }
+    }
+    result
+}
+
+/// Runs the `BeforeApplicationShutdown` hooks of `modules` in reverse dependency order.
+pub(crate) async fn before_shutdown(modules: &[Arc<ResolvedModule>]) -> Result<(), SeptError> {
+    run_all(
+        modules
+            .iter()
+            .rev()
+            .flat_map(|m| m.graph.hooks().before_application_shutdown.iter().rev()),
+    )
+    .await
+}
+
+/// Runs the `OnApplicationShutdown` hooks of `modules` in reverse dependency order.
+pub(crate) async fn shutdown(modules: &[Arc<ResolvedModule>]) -> Result<(), SeptError> {
+    run_all(
+        modules
+            .iter()
+            .rev()
+            .flat_map(|m| m.graph.hooks().application_shutdown.iter().rev()),
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::graph::Graph;
+    use crate::sept_module::{ApplicationContext, Module, ModuleFactory};
+    use crate::Injectable;
+    use std::sync::Mutex;
+
+    fn get_empty_ctx() -> ApplicationContext {
+        ApplicationContext::new(Graph::new())
+    }
+
+    #[actix_rt::test]
+    async fn test_lifecycle_hooks_run_in_dependency_order() {
+        static CALLS: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+        #[derive(Clone, Injectable)]
+        #[injectable(hooks(OnModuleInit, OnApplicationShutdown))]
+        struct Pool;
+
+        impl OnModuleInit for Pool {
+            async fn on_module_init(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("pool init");
+                Ok(())
+            }
+        }
+
+        impl OnApplicationShutdown for Pool {
+            async fn on_application_shutdown(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("pool shutdown");
+                Ok(())
+            }
+        }
+
+        #[derive(Clone, Injectable)]
+        #[injectable(hooks(OnModuleInit, OnApplicationShutdown))]
+        struct Cache {
+            _pool: Arc<Pool>,
+        }
+
+        impl OnModuleInit for Cache {
+            async fn on_module_init(&self) -> Result<(), HookError> {
+                actix_rt::task::yield_now().await;
+                CALLS.lock().unwrap().push("cache init");
+                Ok(())
+            }
+        }
+
+        impl OnApplicationShutdown for Cache {
+            async fn on_application_shutdown(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("cache shutdown");
+                Ok(())
+            }
+        }
+
+        struct HooksModule;
+
+        impl ModuleFactory for HooksModule {
+            fn get_module() -> Module {
+                Module::new().provide::<Cache>().provide::<Pool>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        ctx.build_root::<HooksModule>().await.unwrap();
+        bootstrap(&ctx.order).await.unwrap();
+        shutdown(&ctx.order).await.unwrap();
+        assert_eq!(
+            *CALLS.lock().unwrap(),
+            ["pool init", "cache init", "cache shutdown", "pool shutdown"]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_factory_and_value_providers_run_lifecycle_hooks() {
+        static CALLS: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+        struct Pool;
+
+        impl OnModuleInit for Pool {
+            async fn on_module_init(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("pool init");
+                Ok(())
+            }
+        }
+
+        impl OnApplicationShutdown for Pool {
+            async fn on_application_shutdown(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("pool shutdown");
+                Ok(())
+            }
+        }
+
+        struct Metrics;
+
+        impl OnApplicationShutdown for Metrics {
+            async fn on_application_shutdown(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("metrics shutdown");
+                Ok(())
+            }
+        }
+
+        struct Settings;
+
+        impl OnApplicationShutdown for Settings {
+            async fn on_application_shutdown(&self) -> Result<(), HookError> {
+                CALLS.lock().unwrap().push("settings shutdown");
+                Ok(())
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide_factory(|(): ()| Metrics)
+            .provide_async_factory(|(): ()| async { Ok::<_, HookError>(Pool) })
+            .provide_val(Arc::new(Settings))
+            .with_hooks::<Pool>(|pool, hooks| {
+                hooks.on_module_init(pool.clone());
+                hooks.on_application_shutdown(pool.clone());
+            })
+            .with_hooks::<Metrics>(|metrics, hooks| hooks.on_application_shutdown(metrics.clone()))
+            .with_hooks::<Settings>(|settings, hooks| {
+                hooks.on_application_shutdown(settings.clone())
+            })
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let modules = [Arc::new(resolved)];
+        bootstrap(&modules).await.unwrap();
+        shutdown(&modules).await.unwrap();
+        assert_eq!(
+            *CALLS.lock().unwrap(),
+            [
+                "pool init",
+                "metrics shutdown",
+                "pool shutdown",
+                "settings shutdown"
+            ]
+        );
+    }
+}
@@ -0,0 +1,457 @@
+use crate::error::SeptError;
+use crate::exception::BadRequest;
+use crate::graph::{Graph, Injected};
+use crate::sept_module::ModuleRef;
+use actix_web::web::{Form, Json, Path, Query};
+use std::any::Any;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Where a handler argument is extracted from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgumentKind {
+    /// `web::Path`
+    Param,
+    /// `web::Query`
+    Query,
+    /// `web::Json` or `web::Form`
+    Body,
+    /// Any other extractor.
+    Custom,
+}
+
+/// The handler argument a pipe is transforming.
+#[derive(Clone, Copy, Debug)]
+pub struct ArgumentMetadata {
+    /// The handler, e.g. `UsersClient::show`.
+    pub handler: &'static str,
+    /// The argument as declared, e.g. `id`.
+    pub name: &'static str,
+    pub kind: ArgumentKind,
+}
+
+/// Transforms or validates an extracted handler argument before the handler is called.
+///
+/// Pipes are declared with `#[pipe(TrimPipe, ...)]` on a handler argument and
+/// run in that order. For `web::Path`, `web::Query`, `web::Json` and
+/// `web::Form` arguments they transform the extracted value, e.g.
+/// `#[pipe(ParseUuidPipe)] id: web::Path<Uuid>`, and other arguments as a
+/// whole. Pipes of a whole route, client, module or application are
+/// [`ArgumentPipe`]s instead. Return e.g. `exception::BadRequest` to reject
+/// the request with 400 Bad Request. Pipes are resolved like
+/// [`Guard`](crate::guard::Guard)s.
+pub trait Pipe<In, Out> {
+    fn transform(&self, value: In, metadata: &ArgumentMetadata) -> Result<Out, SeptError>;
+}
+
+/// Resolves a pipe from a module, see [`resolve`].
+pub type PipeFactory = fn(&mut ModuleRef) -> Result<(), SeptError>;
+
+/// Injects the pipe `P` from `module`, or builds it from the module's providers.
+pub fn resolve<P>(module: &mut ModuleRef) -> Result<(), SeptError>
+where
+    P: Injected<Output = P> + Send + Sync + 'static,
+{
+    module.resolve_enhancer::<P>().map(drop)
+}
+
+/// The pipe `P` resolved when `module` was built, for the routes of its clients.
+pub fn get<P: Send + Sync + 'static>(module: &ModuleRef) -> Arc<P> {
+    module.enhancer::<P>()
+}
+
+/// Runs the [`Validate`] implementation of the type an [`Argument`] holds.
+pub type Validator = fn(&dyn Any) -> Result<(), Vec<String>>;
+
+/// A path, query or body argument as seen by an [`ArgumentPipe`].
+pub struct Argument<'a> {
+    value: &'a mut dyn Any,
+    validator: Option<Validator>,
+}
+
+impl Argument<'_> {
+    /// The extracted value, if it is a `T`.
+    pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.value.downcast_mut()
+    }
+
+    /// Validates the extracted value, or returns `None` if its type has no [`Validate`] impl.
+    pub fn validate(&self) -> Option<Result<(), Vec<String>>> {
+        self.validator.map(|validate| validate(&*self.value))
+    }
+}
+
+/// Transforms or validates every path, query and body argument of a route in place.
+///
+/// Argument pipes are attached with `#[pipe(TrimPipe)]` on a handler, a
+/// `#[client]` impl or a `#[module]`, or to every route with
+/// `SeptApplication::use_global_pipe`, and run from the application down,
+/// after the [`Pipe`]s of the argument itself. They see
+/// every argument with the type the handler takes, so they leave the types
+/// they do not handle as they are, e.g. [`TrimPipe`] only trims `String`s.
+pub trait ArgumentPipe {
+    fn transform(
+        &self,
+        argument: Argument<'_>,
+        metadata: &ArgumentMetadata,
+    ) -> Result<(), SeptError>;
+}
+
+/// A resolved argument pipe, see [`resolve_argument_pipe`].
+pub type ArgumentPipeFn = Arc<dyn ArgumentPipe + Send + Sync>;
+
+/// Resolves an argument pipe from a module, see [`resolve_argument_pipe`].
+pub type ArgumentPipeFactory = fn(&mut ModuleRef) -> Result<ArgumentPipeFn, SeptError>;
+
+/// Injects the argument pipe `P` from `module`, or builds it from the module's providers.
+pub fn resolve_argument_pipe<P>(module: &mut ModuleRef) -> Result<ArgumentPipeFn, SeptError>
+where
+    P: ArgumentPipe + Injected<Output = P> + Send + Sync + 'static,
+{
+    Ok(module.resolve_enhancer::<P>()?)
+}
+
+/// The argument pipe `P` resolved when `module` was built, for the routes of its clients.
+pub fn get_argument_pipe<P>(module: &ModuleRef) -> ArgumentPipeFn
+where
+    P: ArgumentPipe + Send + Sync + 'static,
+{
+    module.enhancer::<P>()
+}
+
+/// The argument pipes of a route, generated by `#[client]`.
+#[derive(Clone)]
+pub struct ArgumentPipes {
+    pipes: Rc<Vec<ArgumentPipeFn>>,
+}
+
+impl ArgumentPipes {
+    /// Runs the argument pipes of `module`, then `pipes`, for a route.
+    pub fn new(module: &ModuleRef, pipes: Vec<ArgumentPipeFn>) -> Self {
+        let pipes = module.pipes().iter().cloned().chain(pipes).collect();
+        Self {
+            pipes: Rc::new(pipes),
+        }
+    }
+
+    /// Runs the pipes over `value`, which `validator` validates if it implements [`Validate`].
+    pub fn transform<T: 'static>(
+        &self,
+        value: &mut T,
+        validator: Option<Validator>,
+        metadata: &ArgumentMetadata,
+    ) -> Result<(), SeptError> {
+        for pipe in self.pipes.iter() {
+            let argument = Argument {
+                value: &mut *value,
+                validator,
+            };
+            pipe.transform(argument, metadata)?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks up the [`Validator`] of `T` at compile time, generated by `#[client]`.
+///
+/// `(&&Probe::<T>(PhantomData)).validator()` resolves to [`ViaValidate`] if
+/// `T` implements [`Validate`], and to [`WithoutValidate`] otherwise.
+#[doc(hidden)]
+pub struct Probe<T>(pub PhantomData<T>);
+
+#[doc(hidden)]
+pub trait ViaValidate {
+    fn validator(&self) -> Option<Validator>;
+}
+
+impl<T: Validate + 'static> ViaValidate for &Probe<T> {
+    fn validator(&self) -> Option<Validator> {
+        Some(validate::<T>)
+    }
+}
+
+#[doc(hidden)]
+pub trait WithoutValidate {
+    fn validator(&self) -> Option<Validator>;
+}
+
+impl<T> WithoutValidate for Probe<T> {
+    fn validator(&self) -> Option<Validator> {
+        None
+    }
+}
+
+fn validate<T: Validate + 'static>(value: &dyn Any) -> Result<(), Vec<String>> {
+    value.downcast_ref::<T>().map_or(Ok(()), T::validate)
+}
+
+/// An extractor whose extracted value pipes transform, keeping the extractor.
+pub trait Extracted {
+    type Inner;
+    type With<U>;
+
+    fn pipe<U>(
+        self,
+        transform: impl FnOnce(Self::Inner) -> Result<U, SeptError>,
+    ) -> Result<Self::With<U>, SeptError>;
+}
+
+impl<T> Extracted for Path<T> {
+    type Inner = T;
+    type With<U> = Path<U>;
+
+    fn pipe<U>(
+        self,
+        transform: impl FnOnce(T) -> Result<U, SeptError>,
+    ) -> Result<Path<U>, SeptError> {
+        transform(self.into_inner()).map(Path::from)
+    }
+}
+
+impl<T> Extracted for Query<T> {
+    type Inner = T;
+    type With<U> = Query<U>;
+
+    fn pipe<U>(
+        self,
+        transform: impl FnOnce(T) -> Result<U, SeptError>,
+    ) -> Result<Query<U>, SeptError> {
+        transform(self.0).map(Query)
+    }
+}
+
+impl<T> Extracted for Json<T> {
+    type Inner = T;
+    type With<U> = Json<U>;
+
+    fn pipe<U>(
+        self,
+        transform: impl FnOnce(T) -> Result<U, SeptError>,
+    ) -> Result<Json<U>, SeptError> {
+        transform(self.0).map(Json)
+    }
+}
+
+impl<T> Extracted for Form<T> {
+    type Inner = T;
+    type With<U> = Form<U>;
+
+    fn pipe<U>(
+        self,
+        transform: impl FnOnce(T) -> Result<U, SeptError>,
+    ) -> Result<Form<U>, SeptError> {
+        transform(self.0).map(Form)
+    }
+}
+
+/// Implements `Injected` for a pipe without dependencies.
+macro_rules! stateless {
+    ($pipe:ident) => {
+        impl Injected for $pipe {
+            type Output = Self;
+
+            fn resolve(_: &mut Graph, _: &[&Graph]) -> Result<Self, SeptError> {
+                Ok(Self)
+            }
+        }
+    };
+}
+
+/// Parses a string argument as a UUID.
+pub struct ParseUuidPipe;
+
+stateless!(ParseUuidPipe);
+
+impl Pipe<String, Uuid> for ParseUuidPipe {
+    fn transform(&self, value: String, metadata: &ArgumentMetadata) -> Result<Uuid, SeptError> {
+        Uuid::parse_str(&value)
+            .map_err(|_| BadRequest::new(format!("`{}` must be a UUID", metadata.name)).into())
+    }
+}
+
+/// Trims the whitespace around a string argument.
+pub struct TrimPipe;
+
+stateless!(TrimPipe);
+
+impl Pipe<String, String> for TrimPipe {
+    fn transform(&self, value: String, _: &ArgumentMetadata) -> Result<String, SeptError> {
+        Ok(value.trim().to_owned())
+    }
+}
+
+impl ArgumentPipe for TrimPipe {
+    fn transform(&self, mut argument: Argument<'_>, _: &ArgumentMetadata) -> Result<(), SeptError> {
+        if let Some(value) = argument.downcast_mut::<String>() {
+            *value = value.trim().to_owned();
+        }
+        Ok(())
+    }
+}
+
+/// A value that can check its own invariants, see [`ValidationPipe`].
+pub trait Validate {
+    /// Describes every violated invariant.
+    fn validate(&self) -> Result<(), Vec<String>>;
+}
+
+/// Rejects arguments whose [`Validate`] implementation reports violations.
+pub struct ValidationPipe;
+
+stateless!(ValidationPipe);
+
+impl ValidationPipe {
+    fn reject(violations: Vec<String>, metadata: &ArgumentMetadata) -> SeptError {
+        BadRequest::new(format!(
+            "`{}` is invalid: {}",
+            metadata.name,
+            violations.join(", ")
+        ))
+        .into()
+    }
+}
+
+impl<T: Validate> Pipe<T, T> for ValidationPipe {
+    fn transform(&self, value: T, metadata: &ArgumentMetadata) -> Result<T, SeptError> {
+        match value.validate() {
+            Ok(()) => Ok(value),
+            Err(violations) => Err(Self::reject(violations, metadata)),
+        }
+    }
+}
+
+/// Validates the arguments whose type implements [`Validate`], leaving the others as they are.
+impl ArgumentPipe for ValidationPipe {
+    fn transform(
+        &self,
+        argument: Argument<'_>,
+        metadata: &ArgumentMetadata,
+    ) -> Result<(), SeptError> {
+        match argument.validate() {
+            Some(Err(violations)) => Err(Self::reject(violations, metadata)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_application::SeptApplication;
+    use crate::testing::TestApp;
+    use crate::Injectable;
+
+    #[derive(Injectable)]
+    struct Hashtag;
+
+    impl ArgumentPipe for Hashtag {
+        fn transform(
+            &self,
+            mut argument: Argument<'_>,
+            _: &ArgumentMetadata,
+        ) -> Result<(), SeptError> {
+            if let Some(tag) = argument.downcast_mut::<String>() {
+                tag.insert(0, '#');
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct NotesClient;
+
+    #[crate::client("/notes")]
+    #[pipe(Hashtag)]
+    impl NotesClient {
+        #[get("/{id}")]
+        async fn show(
+            _client: Self,
+            #[pipe(TrimPipe, ParseUuidPipe)] id: actix_web::web::Path<uuid::Uuid>,
+        ) -> String {
+            id.into_inner().to_string()
+        }
+
+        #[get("/tags/{tag}")]
+        async fn tagged(_client: Self, tag: actix_web::web::Path<String>) -> String {
+            tag.into_inner()
+        }
+
+        #[get("/pages/{page}")]
+        #[pipe(TrimPipe)]
+        async fn page(_client: Self, page: actix_web::web::Path<u32>) -> String {
+            page.into_inner().to_string()
+        }
+    }
+
+    #[derive(serde::Deserialize)]
+    struct Draft {
+        title: String,
+    }
+
+    impl Validate for Draft {
+        fn validate(&self) -> Result<(), Vec<String>> {
+            match self.title.is_empty() {
+                true => Err(vec![String::from("title must not be empty")]),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct DraftsClient;
+
+    #[crate::client("/drafts")]
+    impl DraftsClient {
+        #[post("")]
+        async fn create(_client: Self, draft: actix_web::web::Json<Draft>) -> String {
+            draft.into_inner().title
+        }
+    }
+
+    #[crate::module]
+    #[clients(NotesClient, DraftsClient)]
+    #[pipe(ValidationPipe)]
+    struct NotesModule;
+
+    #[actix_rt::test]
+    async fn test_pipes_transform_and_validate_handler_arguments() {
+        let application = SeptApplication::default().use_global_pipe::<TrimPipe>();
+        let app = TestApp::create::<NotesModule>(application).await.unwrap();
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let shown = app
+            .get(&format!("/notes/%20{}%20", id))
+            .send()
+            .await
+            .assert_status(200);
+        assert_eq!(shown.text(), id);
+        let invalid = app.get("/notes/7").send().await.assert_status(400);
+        assert_eq!(
+            invalid.json::<serde_json::Value>(),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+                "detail": "`id` must be a UUID",
+                "instance": "/notes/7",
+            })
+        );
+        // Argument pipes run from the application down, over the types they handle.
+        let tagged = app.get("/notes/tags/%20rust%20").send().await;
+        assert_eq!(tagged.assert_status(200).text(), "#rust");
+        let page = app.get("/notes/pages/3").send().await;
+        assert_eq!(page.assert_status(200).text(), "3");
+
+        let draft = serde_json::json!({ "title": "Pipes" });
+        let created = app.post("/drafts").json(&draft).send().await;
+        assert_eq!(created.assert_status(200).text(), "Pipes");
+        let empty = serde_json::json!({ "title": "" });
+        let rejected = app.post("/drafts").json(&empty).send().await;
+        assert_eq!(
+            rejected.assert_status(400).json::<serde_json::Value>()["detail"],
+            "`draft` is invalid: title must not be empty"
+        );
+    }
+}
@@ -1,32 +1,60 @@
-use crate::graph::Graph;
+use crate::error::SeptError;
+use crate::filter::{FilterFactory, FilterSet};
+use crate::graph::{Graph, Injected};
+use crate::inspect::{ModuleGraph, RouteInfo};
+use crate::instrumentation::logger::Logger;
 use crate::instrumentation::InstrumentationOpts;
+use crate::interceptor::{self, Interceptor, InterceptorFactory};
+use crate::lifecycle;
+use crate::pipe::{self, ArgumentPipe, ArgumentPipeFactory};
 use crate::sept_module::{ApplicationContext, ModuleFactory, ResolvedModule};
+use crate::shutdown::ShutdownHandle;
+use crate::testing::TestApp;
 use actix_cors::Cors;
 use actix_tls::accept::rustls::reexports::ServerConfig;
-use actix_web::web::ServiceConfig;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::web::{Data, ServiceConfig};
 use actix_web::{App as ActixApp, HttpServer};
+use futures_util::future::{select, Either};
 use listenfd::ListenFd;
-use std::collections::HashMap;
-use std::{io, sync::Arc};
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct SeptConfig {
     pub port: u16,
     pub tls_config: Option<ServerConfig>,
+    /// Upper bound on how long building the module graph may take, async factories included.
+    pub startup_timeout: Option<Duration>,
+    /// How long in-flight requests may take to finish once shutdown starts, rounded up to
+    /// whole seconds; actix's default is 30s.
+    pub shutdown_timeout: Option<Duration>,
+    /// Whether SIGINT and SIGTERM (Ctrl-C on other platforms) shut the application down.
+    pub handle_signals: bool,
 }
 
 impl SeptConfig {
     fn new() -> Self {
-        SeptConfig {
+        Self {
             port: 3000,
             tls_config: None,
+            startup_timeout: None,
+            shutdown_timeout: None,
+            handle_signals: true,
         }
     }
 
-    fn register_globals(&mut self) -> ApplicationContext {
-        ApplicationContext {
-            global_providers: Graph::new(),
-            modules: HashMap::new(),
+    pub(crate) fn register_globals(
+        &mut self,
+        shutdown: &ShutdownHandle,
+        logger: Option<&Logger>,
+    ) -> ApplicationContext {
+        let mut globals = Graph::new();
+        globals.provide(Arc::new(Arc::new(shutdown.clone())));
+        if let Some(logger) = logger {
+            globals.provide(Arc::new(logger.clone()));
         }
+        ApplicationContext::new(globals)
     }
 }
 
@@ -44,6 +72,9 @@ pub struct SeptApplication {
     app_config: SeptConfig,
     cors: CorsConfig,
     instrumentation: Option<InstrumentationOpts>,
+    interceptors: Vec<InterceptorFactory>,
+    pipes: Vec<ArgumentPipeFactory>,
+    filters: Vec<FilterFactory>,
 }
 
 impl SeptApplication {
@@ -53,16 +84,53 @@ impl SeptApplication {
             app_config,
             cors: CorsConfig::default(),
             instrumentation: None,
+            interceptors: Vec::new(),
+            pipes: Vec::new(),
+            filters: Vec::new(),
         }
     }
 
-    fn configure(module: Arc<ResolvedModule>, config: &mut ServiceConfig) {
-        for client in &module.clients {
-            client.register(config);
+    fn configure(modules: &[Arc<ResolvedModule>], config: &mut ServiceConfig) {
+        for module in modules {
+            for client in &module.clients {
+                client.register(config, &module.module_ref);
+            }
         }
-        for imports in &module.imports {
-            Self::configure(imports.clone(), config);
+    }
+
+    /// The actix application serving the clients of every module, with CORS and instrumentation
+    pub(crate) fn app(
+        cors_config: CorsConfig,
+        logger: Option<Logger>,
+        modules: Arc<[Arc<ResolvedModule>]>,
+    ) -> ActixApp<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        let cors = Cors::default()
+            .allowed_origin(&cors_config.allowed_origin)
+            .allowed_methods(cors_config.allowed_methods)
+            .allowed_headers(cors_config.allowed_headers)
+            .expose_headers(cors_config.expose_headers)
+            .max_age(cors_config.max_age);
+
+        let mut app = ActixApp::new();
+        if let Some(logger) = logger {
+            app = app.app_data(Data::new(logger));
         }
+        app.wrap(cors).configure(|cfg| Self::configure(&modules, cfg))
+    }
+
+    /// Takes the configured instrumentation as the `Logger` handed to providers and handlers
+    fn logger(&mut self) -> Option<Logger> {
+        self.instrumentation
+            .take()
+            .map(|opts| Logger::new(Arc::from(opts.provider), opts.level))
     }
 
     /// Method to enable default instrumentation for the application
@@ -82,40 +150,192 @@ impl SeptApplication {
         self
     }
 
-    pub async fn init<T: ModuleFactory>(mut self) -> io::Result<()> {
+    /// Wraps every route of the application in `I`, outside of module and client interceptors
+    ///
+    /// `I` is resolved in every module with clients, so its dependencies must be visible there
+    pub fn use_global_interceptor<I>(mut self) -> Self
+    where
+        I: Interceptor + Injected<Output = I> + Send + Sync + 'static,
+    {
+        self.interceptors.push(interceptor::resolve::<I>);
+        self
+    }
+
+    /// Runs `P` over the path, query and body arguments of every route, before module and
+    /// client pipes
+    ///
+    /// Like global interceptors, `P` is resolved in every module with clients
+    pub fn use_global_pipe<P>(mut self) -> Self
+    where
+        P: ArgumentPipe + Injected<Output = P> + Send + Sync + 'static,
+    {
+        self.pipes.push(pipe::resolve_argument_pipe::<P>);
+        self
+    }
+
+    /// Catches the errors of every route with `F`, a filter or a tuple of them, after the
+    /// route's and module's filters
+    ///
+    /// Like global interceptors, the filters are resolved in every module with clients
+    pub fn use_global_filters<F: FilterSet>(mut self) -> Self {
+        self.filters.extend(F::factories());
+        self
+    }
+
+    /// Builds the module graph rooted at `T` and runs its startup hooks
+    async fn build<T: ModuleFactory + 'static>(
+        &mut self,
+        shutdown: &ShutdownHandle,
+        logger: Option<&Logger>,
+    ) -> Result<SeptContext, SeptError> {
+        let mut ctx: ApplicationContext = self.app_config.register_globals(shutdown, logger);
+        ctx.interceptors = self.interceptors.clone();
+        ctx.pipes = self.pipes.clone();
+        ctx.filters = self.filters.clone();
+        match self.app_config.startup_timeout {
+            Some(timeout) => actix_rt::time::timeout(timeout, SeptContext::build::<T>(ctx))
+                .await
+                .map_err(|_| SeptError::StartupTimeout(timeout))?,
+            None => SeptContext::build::<T>(ctx).await,
+        }
+    }
+
+    /// Builds the module graph rooted at `T` without serving it, e.g. for CLI tools and workers
+    pub async fn create_context<T: ModuleFactory + 'static>(
+        mut self,
+    ) -> Result<SeptContext, SeptError> {
+        let logger = self.logger();
+        self.build::<T>(&ShutdownHandle::new(), logger.as_ref()).await
+    }
+
+    /// Builds the module graph rooted at `T` and serves it in-process, see [`TestApp`]
+    pub(crate) async fn test_app<T: ModuleFactory + 'static>(
+        mut self,
+    ) -> Result<TestApp, SeptError> {
+        let logger = self.logger();
+        let context = self.build::<T>(&ShutdownHandle::new(), logger.as_ref()).await?;
+        Ok(TestApp::new(Self::app(self.cors, logger, context.modules.clone()), context).await)
+    }
+
+    /// Serves the already built `context` in-process, see [`TestApp`]
+    pub(crate) async fn test_app_with(mut self, context: SeptContext) -> TestApp {
+        let logger = self.logger();
+        TestApp::new(Self::app(self.cors, logger, context.modules.clone()), context).await
+    }
+
+    /// Builds the module graph rooted at `T` and serves its clients until the server stops,
+    /// running the lifecycle hooks of its providers around it
+    pub async fn init<T: ModuleFactory + 'static>(mut self) -> Result<(), SeptError> {
         let mut fd = ListenFd::from_env();
-        let mut ctx: ApplicationContext = self.app_config.register_globals();
-        let module = Arc::new(T::get_module().build(&mut ctx));
+        let shutdown = ShutdownHandle::new();
+        let logger = self.logger();
+        let context = self.build::<T>(&shutdown, logger.as_ref()).await?;
+        for route in context.routes() {
+            let RouteInfo { method, path, handler, .. } = route;
+            tracing::info!("Mapped {{{}, {}}} route to {}", path, method, handler);
+        }
+        let modules = context.modules;
+        let served = modules.clone();
+        let cors = self.cors.clone();
         let mut server = HttpServer::new(move || {
-            let cors_config = self.cors.clone();
-            let cors = Cors::default()
-                .allowed_origin(&cors_config.allowed_origin)
-                .allowed_methods(cors_config.allowed_methods)
-                .allowed_headers(cors_config.allowed_headers)
-                .expose_headers(cors_config.expose_headers)
-                .max_age(cors_config.max_age);
-
-            ActixApp::new().wrap(cors).configure(|cfg| Self::configure(module.clone(), cfg))
-        });
-
-        if cfg!(feature = "rustls") && self.app_config.tls_config.is_some() {
-            server = match fd.take_tcp_listener(0).unwrap() {
-                Some(listener) => {
-                    server.listen_rustls(listener, self.app_config.tls_config.unwrap())?
+            Self::app(cors.clone(), logger.clone(), served.clone())
+        })
+        .disable_signals();
+        if let Some(timeout) = self.app_config.shutdown_timeout {
+            // actix takes whole seconds; round up so sub-second timeouts still drain requests.
+            let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+            server = server.shutdown_timeout(secs);
+        }
+
+        match self.app_config.tls_config {
+            Some(tls_config) if cfg!(feature = "rustls") => {
+                server = match fd.take_tcp_listener(0)? {
+                    Some(listener) => server.listen_rustls(listener, tls_config)?,
+                    None => server
+                        .bind_rustls(format!("0.0.0.0:{}", self.app_config.port), tls_config)?,
                 }
-                None => server.bind_rustls(
-                    format!("0.0.0.0:{}", self.app_config.port),
-                    self.app_config.tls_config.unwrap(),
-                )?,
             }
-        } else {
-            server = match fd.take_tcp_listener(0).unwrap() {
-                Some(listener) => server.listen(listener)?,
-                None => server.bind(format!("0.0.0.0:{}", self.app_config.port))?,
-            };
+            _ => {
+                server = match fd.take_tcp_listener(0)? {
+                    Some(listener) => server.listen(listener)?,
+                    None => server.bind(format!("0.0.0.0:{}", self.app_config.port))?,
+                };
+            }
         }
 
-        server.run().await
+        let server = server.run();
+        let handle = server.handle();
+        let mut serving = actix_rt::spawn(server);
+        let requested = shutdown.requested(self.app_config.handle_signals);
+        let (before, served) = match select(Box::pin(requested), &mut serving).await {
+            Either::Left(((), _)) => {
+                let before = lifecycle::before_shutdown(&modules).await;
+                handle.stop(true).await;
+                (before, serving.await)
+            }
+            Either::Right((served, _)) => (lifecycle::before_shutdown(&modules).await, served),
+        };
+        let after = lifecycle::shutdown(&modules).await;
+        served.map_err(std::io::Error::other)??;
+        before.and(after)
+    }
+}
+
+/// A built module graph without an HTTP server, see [`SeptApplication::create_context`].
+pub struct SeptContext {
+    pub(crate) root: Arc<ResolvedModule>,
+    modules: Arc<[Arc<ResolvedModule>]>,
+}
+
+impl SeptContext {
+    /// Builds the module graph rooted at `T` in `ctx` and runs its startup hooks
+    pub(crate) async fn build<T: ModuleFactory + 'static>(
+        mut ctx: ApplicationContext,
+    ) -> Result<Self, SeptError> {
+        let root = ctx.build_root::<T>().await?;
+        let modules = std::mem::take(&mut ctx.order);
+        lifecycle::bootstrap(&modules).await?;
+        Ok(Self {
+            root,
+            modules: modules.into(),
+        })
+    }
+
+    /// Returns the provider or client `T` of the root module, or else of the first module providing it
+    pub fn get<T: Clone + 'static>(&self) -> Result<T, SeptError> {
+        let mut found = self.root.module_ref.get::<T>();
+        for module in self.modules.iter() {
+            match found {
+                Err(SeptError::MissingProvider { .. }) => found = module.module_ref.get::<T>(),
+                _ => break,
+            }
+        }
+        found
+    }
+
+    /// Describes every module of the application, in dependency order
+    pub fn graph(&self) -> ModuleGraph {
+        ModuleGraph {
+            modules: self.modules.iter().map(|m| m.info.clone()).collect(),
+        }
+    }
+
+    /// Lists the routes of every client, in module dependency order
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        self.modules.iter().flat_map(|m| m.info.routes.clone()).collect()
+    }
+
+    /// Runs the shutdown hooks of every module
+    pub async fn close(self) -> Result<(), SeptError> {
+        let before = lifecycle::before_shutdown(&self.modules).await;
+        let after = lifecycle::shutdown(&self.modules).await;
+        before.and(after)
+    }
+}
+
+impl Default for SeptConfig {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -126,8 +346,8 @@ impl Default for SeptApplication {
 }
 
 impl Default for CorsConfig {
-    fn default() -> CorsConfig {
-        CorsConfig {
+    fn default() -> Self {
+        Self {
             allowed_origin: "http://localhost:3000".to_string(),
             allowed_methods: vec![actix_http::Method::GET, actix_http::Method::POST, actix_http::Method::PUT, actix_http::Method::DELETE],
             allowed_headers: vec![
@@ -140,4 +360,57 @@ impl Default for CorsConfig {
             expose_headers: vec![],
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_module::Module;
+    use crate::Injectable;
+
+    #[derive(Clone, Injectable)]
+    struct Settings;
+
+    #[derive(Clone, Injectable)]
+    struct Greeter {
+        _settings: Arc<Settings>,
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Mailer;
+
+    struct SettingsModule;
+
+    impl ModuleFactory for SettingsModule {
+        fn get_module() -> Module {
+            Module::new().global().provide::<Settings>().export::<Settings>()
+        }
+    }
+
+    struct GreeterModule;
+
+    impl ModuleFactory for GreeterModule {
+        fn get_module() -> Module {
+            Module::new().provide::<Greeter>()
+        }
+    }
+
+    struct AppModule;
+
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new().import::<GreeterModule>().import::<SettingsModule>()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_standalone_context_resolves_providers() {
+        let context = SeptApplication::default().create_context::<AppModule>().await.unwrap();
+        context.get::<Arc<Greeter>>().unwrap();
+        context.get::<Arc<Settings>>().unwrap();
+        let err = context.get::<Arc<Mailer>>().err().expect("expected a missing provider error");
+        assert!(matches!(err, SeptError::MissingProvider { .. }), "{}", err);
+        context.close().await.unwrap();
+    }
+}
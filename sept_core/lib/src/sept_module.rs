@@ -1,59 +1,709 @@
-use crate::graph::{Graph, Injected};
+use crate::error::SeptError;
+use crate::filter::{self, ExceptionFilter, FilterFactory, FilterFn};
+use crate::graph::{Dependencies, Graph, Injected, Interface, Multi, Scope, Token};
+use crate::guard::{self, Guard, GuardFactory, GuardFn};
+use crate::inspect::{self, ModuleInfo, RouteInfo};
+use crate::interceptor::{self, Interceptor, InterceptorFactory, InterceptorFn};
+use crate::lifecycle::Hooks;
+use crate::pipe::{self, ArgumentPipe, ArgumentPipeFactory, ArgumentPipeFn, PipeFactory};
 use actix_web::web::ServiceConfig;
-use std::sync::Arc;
+use actix_web::{FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use std::sync::{Arc, OnceLock};
 use std::{
-    any::TypeId,
+    any::{Any, TypeId},
     collections::{HashMap, HashSet},
 };
 
 pub trait ServiceFactory: Send + Sync {
-    fn register(&self, app: &mut ServiceConfig);
+    fn register(&self, app: &mut ServiceConfig, module: &ModuleRef);
+
+    /// The routes `register` serves, generated by `#[client]`.
+    fn routes(&self) -> Vec<RouteInfo> {
+        Vec::new()
+    }
+
+    /// The `#[guard(...)]`s of the client and its handlers, resolved once when its module is built.
+    fn guards(&self) -> Vec<GuardFactory> {
+        Vec::new()
+    }
+
+    /// The `#[interceptor(...)]`s of the client and its handlers, resolved like its guards.
+    fn interceptors(&self) -> Vec<InterceptorFactory> {
+        Vec::new()
+    }
+
+    /// The `#[pipe(...)]`s of the client, its handlers and arguments, resolved like its guards.
+    fn pipes(&self) -> Vec<PipeFactory> {
+        Vec::new()
+    }
+
+    /// The `#[filter(...)]`s of the client and its handlers, resolved like its guards.
+    fn filters(&self) -> Vec<FilterFactory> {
+        Vec::new()
+    }
+}
+
+/// A built module's providers, as seen by its clients while serving requests.
+#[derive(Clone, Default)]
+pub struct ModuleRef {
+    graph: Arc<Graph>,
+    imports: Arc<Vec<Graph>>,
+    guards: Arc<Vec<GuardFn>>,
+    interceptors: Arc<Vec<InterceptorFn>>,
+    pipes: Arc<Vec<ArgumentPipeFn>>,
+    filters: Arc<Vec<FilterFn>>,
+    /// The guards, interceptors, pipes and filters resolved when the module was built, by type.
+    enhancers: Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+    /// The exports of the modules imported with `Module::forward_ref`, set once they are built.
+    forward: Arc<OnceLock<Vec<Graph>>>,
+}
+
+impl ModuleRef {
+    fn new(graph: Graph, imports: &[&Graph]) -> Self {
+        Self {
+            graph: Arc::new(graph),
+            imports: Arc::new(imports.iter().map(|&graph| graph.clone()).collect()),
+            guards: Arc::default(),
+            interceptors: Arc::default(),
+            pipes: Arc::default(),
+            filters: Arc::default(),
+            enhancers: Arc::default(),
+            forward: Arc::default(),
+        }
+    }
+
+    /// The guards of the module, run before those of its clients.
+    pub(crate) fn guards(&self) -> &[GuardFn] {
+        &self.guards
+    }
+
+    /// The global interceptors, then those of the module, wrapping those of its clients.
+    pub(crate) fn interceptors(&self) -> &[InterceptorFn] {
+        &self.interceptors
+    }
+
+    /// The global argument pipes, then those of the module, run before those of its clients.
+    pub(crate) fn pipes(&self) -> &[ArgumentPipeFn] {
+        &self.pipes
+    }
+
+    /// The filters of the module, then the global filters, tried after those of its clients.
+    pub(crate) fn filters(&self) -> &[FilterFn] {
+        &self.filters
+    }
+
+    /// Injects the guard, interceptor, pipe or filter `T`, or builds it, once per module.
+    pub(crate) fn resolve_enhancer<T>(&mut self) -> Result<Arc<T>, SeptError>
+    where
+        T: Injected<Output = T> + Send + Sync + 'static,
+    {
+        if let Some(enhancer) = self.enhancers.get(&TypeId::of::<T>()) {
+            return Ok(enhancer
+                .clone()
+                .downcast()
+                .expect("enhancers are keyed by type"));
+        }
+        let enhancer = self.get_or_build::<T>()?;
+        Arc::make_mut(&mut self.enhancers).insert(TypeId::of::<T>(), enhancer.clone());
+        Ok(enhancer)
+    }
+
+    /// The `T` that `resolve_enhancer` resolved when the module was built.
+    pub(crate) fn enhancer<T: Send + Sync + 'static>(&self) -> Arc<T> {
+        self.enhancers
+            .get(&TypeId::of::<T>())
+            .and_then(|enhancer| enhancer.clone().downcast().ok())
+            .expect("the enhancers of a client are resolved when its module is built")
+    }
+
+    fn import_refs(&self) -> Vec<&Graph> {
+        self.imports
+            .iter()
+            .chain(self.forward.get().into_iter().flatten())
+            .collect()
+    }
+
+    /// Returns the provider `T`, building a new instance if it is transient.
+    pub fn get<T: Clone + 'static>(&self) -> Result<T, SeptError> {
+        let imports = self.import_refs();
+        if let Some(node) = self
+            .graph
+            .get_node::<T>()
+            .or_else(|| Graph::search_all(&imports))
+        {
+            return Ok(node.clone());
+        }
+        Graph::clone(&self.graph).filling_lazy(&imports, |graph| graph.inject::<T>(&imports))
+    }
+
+    /// Returns the provider `Arc<T>`, or else builds a `T` from this module's providers.
+    pub fn get_or_build<T>(&self) -> Result<Arc<T>, SeptError>
+    where
+        T: Injected<Output = T> + Send + Sync + 'static,
+    {
+        let imports = self.import_refs();
+        let mut graph = Graph::clone(&self.graph);
+        graph.filling_lazy(&imports, |graph| {
+            match graph.inject_optional::<Arc<T>>(&imports)? {
+                Some(provided) => Ok(provided),
+                None => T::resolve(graph, &imports).map(Arc::new),
+            }
+        })
+    }
+
+    /// Resolves `T` in the graph of the request `req`, creating that graph on first use.
+    fn inject_for_request<T: Clone + 'static>(&self, req: &HttpRequest) -> Result<T, SeptError> {
+        struct RequestGraph(Graph);
+
+        let mut extensions = req.extensions_mut();
+        if extensions.get::<RequestGraph>().is_none() {
+            let mut graph = Graph::for_request();
+            graph.provide(Arc::new(Arc::new(RequestInfo::new(req))));
+            extensions.insert(RequestGraph(graph));
+        }
+        let RequestGraph(graph) = extensions.get_mut::<RequestGraph>().unwrap();
+        let imports: Vec<&Graph> = std::iter::once(&*self.graph)
+            .chain(self.import_refs())
+            .collect();
+        graph.filling_lazy(&imports, |graph| graph.inject::<T>(&imports))
+    }
+}
+
+/// The method, URI and headers of the request being served, injectable into request-scoped providers.
+#[derive(Clone, Debug)]
+pub struct RequestInfo {
+    pub method: actix_web::http::Method,
+    pub uri: actix_web::http::Uri,
+    pub headers: actix_web::http::header::HeaderMap,
+}
+
+impl RequestInfo {
+    fn new(req: &HttpRequest) -> Self {
+        Self {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers: req.headers().clone(),
+        }
+    }
+}
+
+/// Extracts the provider `T` for the current request.
+///
+/// Request-scoped providers are built at most once per request, transient
+/// ones on every extraction. Request-scoped providers can inject the
+/// `RequestInfo` of the request being served.
+pub struct Scoped<T>(Arc<T>);
+
+impl<T> Scoped<T> {
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for Scoped<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Failing to build `T` is logged and answered with 500 Internal Server Error.
+impl<T: Injected<Output = T> + 'static> FromRequest for Scoped<T> {
+    type Error = SeptError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut actix_http::Payload) -> Self::Future {
+        let extracted = match req.app_data::<actix_web::web::Data<ModuleRef>>() {
+            Some(module) => module.inject_for_request::<Arc<T>>(req),
+            None => Err(SeptError::missing::<ModuleRef>()),
+        };
+        ready(extracted.map(Self))
+    }
+}
+
+/// Identifies a built module: its type, plus a key for each configuration of a dynamic module.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct ModuleKey {
+    id: TypeId,
+    dynamic: Option<String>,
+}
+
+impl ModuleKey {
+    fn of<T: 'static>() -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            dynamic: None,
+        }
+    }
 }
 
 pub(crate) struct ApplicationContext {
     pub(crate) global_providers: Graph,
-    pub(crate) modules: HashMap<TypeId, Arc<ResolvedModule>>,
+    pub(crate) modules: HashMap<ModuleKey, Arc<ResolvedModule>>,
+    /// Modules whose build has started but not finished, outermost first.
+    pub(crate) building: Vec<(ModuleKey, &'static str)>,
+    /// Built modules in dependency order, for running lifecycle hooks.
+    pub(crate) order: Vec<Arc<ResolvedModule>>,
+    /// Values replacing the providers of the same token in whichever module provides them.
+    pub(crate) overrides: Graph,
+    /// The tokens of `overrides` some module provides.
+    overridden: HashSet<Token>,
+    /// Modules built in place of the module with the given type.
+    pub(crate) module_overrides: HashMap<TypeId, fn() -> Module>,
+    /// Modules returned by `get_module` while looking for global modules, built from here.
+    discovered: HashMap<ModuleKey, Module>,
+    /// The hash of the options of each dynamic module imported so far.
+    dynamic_options: HashMap<ModuleKey, u64>,
+    /// Interceptors wrapping every route, see `SeptApplication::use_global_interceptor`.
+    pub(crate) interceptors: Vec<InterceptorFactory>,
+    /// Argument pipes of every route, see `SeptApplication::use_global_pipe`.
+    pub(crate) pipes: Vec<ArgumentPipeFactory>,
+    /// Filters catching the errors of every route, see `SeptApplication::use_global_filters`.
+    pub(crate) filters: Vec<FilterFactory>,
+}
+
+impl ApplicationContext {
+    pub(crate) fn new(global_providers: Graph) -> Self {
+        Self {
+            global_providers,
+            modules: HashMap::new(),
+            building: Vec::new(),
+            order: Vec::new(),
+            overrides: Graph::new(),
+            overridden: HashSet::new(),
+            module_overrides: HashMap::new(),
+            discovered: HashMap::new(),
+            dynamic_options: HashMap::new(),
+            interceptors: Vec::new(),
+            pipes: Vec::new(),
+            filters: Vec::new(),
+        }
+    }
+
+    pub(crate) fn build_module<T: ModuleFactory + 'static>(
+        &mut self,
+    ) -> LocalBoxFuture<'_, Result<Arc<ResolvedModule>, SeptError>> {
+        let key = ModuleKey::of::<T>();
+        let discovered = self.discovered.remove(&key);
+        let module = self.module_override::<T>();
+        self.build_keyed(key, std::any::type_name::<T>(), None, move || {
+            Some(discovered.unwrap_or_else(module))
+        })
+    }
+
+    fn module_override<T: ModuleFactory + 'static>(&self) -> fn() -> Module {
+        self.module_overrides
+            .get(&TypeId::of::<T>())
+            .copied()
+            .unwrap_or(T::get_module)
+    }
+
+    /// Builds the module returned by `module` unless a module with the same `key` was already built.
+    ///
+    /// `module` returns `None` once a dynamic module was taken by an earlier
+    /// build of the same import, which can only still be running.
+    fn build_keyed(
+        &mut self,
+        key: ModuleKey,
+        name: &'static str,
+        label: Option<String>,
+        module: impl FnOnce() -> Option<Module> + 'static,
+    ) -> LocalBoxFuture<'_, Result<Arc<ResolvedModule>, SeptError>> {
+        Box::pin(async move {
+            if let Some(resolved) = self.modules.get(&key) {
+                return Ok(resolved.clone());
+            }
+            let start = self.building.iter().position(|(b, _)| *b == key);
+            let Some(module) = start.is_none().then(module).flatten() else {
+                let start = start.unwrap_or(0);
+                let mut path: Vec<_> = self.building[start..].iter().map(|(_, n)| *n).collect();
+                path.push(name);
+                return Err(SeptError::CircularImport { path });
+            };
+
+            self.building.push((key.clone(), name));
+            let built = module.named(name).build(self).await;
+            self.building.pop();
+
+            let mut built = built?;
+            if let Some(label) = label {
+                built.info.name = format!("{}({})", name, label);
+            }
+            let new_module = Arc::new(built);
+            if new_module.global {
+                self.global_providers.merge(&new_module.graphed_exports);
+            }
+            self.modules.insert(key, new_module.clone());
+            self.order.push(new_module.clone());
+            Ok(new_module)
+        })
+    }
+
+    /// Builds the application rooted at `T`, building global modules anywhere in its imports first.
+    pub(crate) async fn build_root<T: ModuleFactory + 'static>(
+        &mut self,
+    ) -> Result<Arc<ResolvedModule>, SeptError> {
+        let mut visited = HashSet::new();
+        let mut globals = Vec::new();
+        let mut discovered = HashMap::new();
+        ImportRef::of::<T>().discover_globals(
+            &self.module_overrides,
+            &mut visited,
+            &mut globals,
+            &mut discovered,
+        );
+        self.discovered = discovered;
+        for global in globals {
+            global.build(self).await?;
+        }
+        let root = self.build_module::<T>().await?;
+        self.resolve_forward_refs().await?;
+        if let Some(unused) = self
+            .overrides
+            .tokens()
+            .find(|token| !self.overridden.contains(token))
+        {
+            return Err(SeptError::UnusedOverride {
+                type_name: unused.type_name(),
+                name: unused.name(),
+            });
+        }
+        Ok(root)
+    }
+
+    /// Builds the modules imported with `Module::forward_ref` that are not built yet, then
+    /// makes their exports visible to the modules referencing them.
+    async fn resolve_forward_refs(&mut self) -> Result<(), SeptError> {
+        let mut index = 0;
+        while let Some(module) = self.order.get(index).cloned() {
+            for import in &module.forward_imports {
+                (import.build)(self).await?;
+            }
+            index += 1;
+        }
+        for module in &self.order {
+            if module.forward_imports.is_empty() {
+                continue;
+            }
+            let forward: Vec<_> = module
+                .forward_imports
+                .iter()
+                .map(|import| self.modules[&import.key].graphed_exports.clone())
+                .collect();
+            let mut graphs = vec![&module.graph];
+            graphs.extend(imported_graphs(&module.imports, self));
+            graphs.extend(&forward);
+            module
+                .graph
+                .fill_deferred_lazy(&graphs)
+                .map_err(|err| err.in_module(module.name))?;
+            let _ = module.module_ref.forward.set(forward);
+        }
+        Ok(())
+    }
+}
+
+/// A module configured at runtime, e.g. by a `DbModule::for_root(options)` constructor.
+///
+/// Import it with `Module::import_dynamic` or by listing the constructor call
+/// in `#[imports(...)]`. It is built once per type and options, so the same
+/// configuration can be imported by several modules.
+pub struct DynamicModule {
+    key: ModuleKey,
+    name: &'static str,
+    label: Option<String>,
+    options: u64,
+    module: Module,
+}
+
+impl DynamicModule {
+    /// Configures the module `T` as `module`, keyed by the `options` it was built from.
+    pub fn new<T: 'static>(options: &impl Hash, module: Module) -> Self {
+        let mut hasher = DefaultHasher::new();
+        options.hash(&mut hasher);
+        let options = hasher.finish();
+        Self {
+            key: ModuleKey {
+                id: TypeId::of::<T>(),
+                dynamic: Some(format!("{:016x}", options)),
+            },
+            name: std::any::type_name::<T>(),
+            label: None,
+            options,
+            module,
+        }
+    }
+
+    /// Names this configuration, e.g. one per `for_feature` call.
+    ///
+    /// Importing the same key with different options fails with
+    /// `SeptError::DynamicModuleConflict`.
+    pub fn keyed(mut self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        self.key.dynamic = Some(key.clone());
+        self.label = Some(key);
+        self
+    }
 }
+
+type BuildModule = for<'a> fn(
+    &'a mut ApplicationContext,
+) -> LocalBoxFuture<'a, Result<Arc<ResolvedModule>, SeptError>>;
+
+/// A module imported with `Module::forward_ref` before it was built.
+#[derive(Clone)]
+struct ForwardImport {
+    key: ModuleKey,
+    build: BuildModule,
+}
+
+/// A module listed in `imports`, kept so global modules can be found before anything is built.
+#[derive(Clone)]
+enum ImportRef {
+    Static {
+        key: ModuleKey,
+        module: fn() -> Module,
+        build: BuildModule,
+    },
+    Dynamic {
+        key: ModuleKey,
+        name: &'static str,
+        label: Option<String>,
+        options: u64,
+        global: bool,
+        imports: Vec<Self>,
+        /// Taken by whichever import builds the module first.
+        module: Rc<RefCell<Option<Module>>>,
+    },
+}
+
+impl ImportRef {
+    fn of<T: ModuleFactory + 'static>() -> Self {
+        Self::Static {
+            key: ModuleKey::of::<T>(),
+            module: T::get_module,
+            build: ApplicationContext::build_module::<T>,
+        }
+    }
+
+    fn dynamic(dynamic: DynamicModule) -> Self {
+        Self::Dynamic {
+            key: dynamic.key,
+            name: dynamic.name,
+            label: dynamic.label,
+            options: dynamic.options,
+            global: dynamic.module.global,
+            imports: dynamic.module.import_refs.clone(),
+            module: Rc::new(RefCell::new(Some(dynamic.module))),
+        }
+    }
+
+    fn build<'a>(
+        &self,
+        ctx: &'a mut ApplicationContext,
+    ) -> LocalBoxFuture<'a, Result<Arc<ResolvedModule>, SeptError>> {
+        match self {
+            Self::Static { build, .. } => build(ctx),
+            Self::Dynamic {
+                key,
+                name,
+                label,
+                options,
+                module,
+                ..
+            } => {
+                let imported = *ctx.dynamic_options.entry(key.clone()).or_insert(*options);
+                if imported != *options {
+                    let conflict = SeptError::DynamicModuleConflict {
+                        name,
+                        key: label.clone().unwrap_or_default(),
+                    };
+                    return Box::pin(ready(Err(conflict)));
+                }
+                let module = module.clone();
+                ctx.build_keyed(key.clone(), name, label.clone(), move || {
+                    module.borrow_mut().take()
+                })
+            }
+        }
+    }
+
+    /// Collects the global modules reachable from this one, dependencies first.
+    ///
+    /// The modules of static imports are kept in `discovered`, so they are built
+    /// without calling `get_module` again.
+    fn discover_globals(
+        &self,
+        overrides: &HashMap<TypeId, fn() -> Module>,
+        visited: &mut HashSet<ModuleKey>,
+        globals: &mut Vec<Self>,
+        discovered: &mut HashMap<ModuleKey, Module>,
+    ) {
+        match self {
+            Self::Static { key, module, .. } => {
+                if !visited.insert(key.clone()) {
+                    return;
+                }
+                let module = overrides.get(&key.id).unwrap_or(module)();
+                for import in &module.import_refs {
+                    import.discover_globals(overrides, visited, globals, discovered);
+                }
+                if module.global {
+                    globals.push(self.clone());
+                }
+                discovered.insert(key.clone(), module);
+            }
+            Self::Dynamic {
+                key,
+                global,
+                imports,
+                ..
+            } => {
+                if !visited.insert(key.clone()) {
+                    return;
+                }
+                for import in imports {
+                    import.discover_globals(overrides, visited, globals, discovered);
+                }
+                if *global {
+                    globals.push(self.clone());
+                }
+            }
+        }
+    }
+}
+
+type BuildStep =
+    Box<dyn FnOnce(&mut ResolvedModule, &mut ApplicationContext) -> Result<(), SeptError>>;
+type AsyncBuildStep = Box<
+    dyn for<'a> FnOnce(
+        &'a mut ResolvedModule,
+        &'a mut ApplicationContext,
+    ) -> LocalBoxFuture<'a, Result<(), SeptError>>,
+>;
+
+/// Boxes `step`, pinning down the higher-ranked signature closures cannot infer on their own.
+fn async_step<F>(step: F) -> AsyncBuildStep
+where
+    F: for<'a> FnOnce(
+            &'a mut ResolvedModule,
+            &'a mut ApplicationContext,
+        ) -> LocalBoxFuture<'a, Result<(), SeptError>>
+        + 'static,
+{
+    Box::new(step)
+}
+
 #[derive(Default)]
 pub struct Module {
-    exports: HashSet<TypeId>,
-    tokens: HashSet<TypeId>,
-    imports: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut ApplicationContext)>>,
-    providers: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut ApplicationContext)>>,
-    provider_vals: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut ApplicationContext)>>,
-    clients: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut ApplicationContext)>>,
+    name: Option<&'static str>,
+    global: bool,
+    import_refs: Vec<ImportRef>,
+    exports: HashSet<Token>,
+    tokens: HashSet<Token>,
+    imports: Vec<AsyncBuildStep>,
+    declarations: Vec<BuildStep>,
+    async_providers: Vec<AsyncBuildStep>,
+    providers: Vec<BuildStep>,
+    provider_vals: Vec<BuildStep>,
+    /// Hooks of factory and value providers, registered before anything is provided.
+    hooks: Vec<BuildStep>,
+    clients: Vec<BuildStep>,
+    guards: Vec<GuardFactory>,
+    interceptors: Vec<InterceptorFactory>,
+    pipes: Vec<ArgumentPipeFactory>,
+    filters: Vec<FilterFactory>,
 }
 
 impl Module {
     pub fn new() -> Self {
         Self {
+            name: None,
+            global: false,
+            import_refs: Vec::new(),
             exports: HashSet::new(),
             tokens: HashSet::new(),
             imports: Vec::new(),
+            declarations: Vec::new(),
+            async_providers: Vec::new(),
             providers: Vec::new(),
             provider_vals: Vec::new(),
+            hooks: Vec::new(),
             clients: Vec::new(),
+            guards: Vec::new(),
+            interceptors: Vec::new(),
+            pipes: Vec::new(),
+            filters: Vec::new(),
         }
     }
 
+    /// Makes this module's exports visible to every module, without importing it.
+    pub fn global(mut self) -> Self {
+        self.global = true;
+        self
+    }
+
     pub fn import<T: ModuleFactory + 'static>(mut self) -> Self {
-        self.imports.push(Box::new(|module, ctx| {
-            if let Some(resolved) = ctx.modules.get(&TypeId::of::<T>()) {
-                module.imports.push(resolved.clone());
-            } else {
-                let new_module = Arc::new(T::get_module().build(ctx));
-                ctx.modules.insert(TypeId::of::<T>(), new_module.clone());
-                module.imports.push(new_module);
-            }
+        self.import_refs.push(ImportRef::of::<T>());
+        self.imports.push(async_step(|module, ctx| {
+            Box::pin(async move {
+                module.imports.push(ctx.build_module::<T>().await?);
+                Ok(())
+            })
+        }));
+        self
+    }
+
+    /// Imports a module configured at runtime, e.g. `DbModule::for_root(options)`.
+    pub fn import_dynamic(mut self, dynamic: DynamicModule) -> Self {
+        let import = ImportRef::dynamic(dynamic);
+        self.import_refs.push(import.clone());
+        self.imports.push(async_step(move |module, ctx| {
+            Box::pin(async move {
+                module.imports.push(import.build(ctx).await?);
+                Ok(())
+            })
+        }));
+        self
+    }
+
+    /// Imports `T` while allowing it to be part of an import cycle, whichever side is built first.
+    ///
+    /// Unless `T` is built already, it is built after the rest of the
+    /// application, so its exports are only visible to this module's `Lazy`
+    /// injections and to its clients while serving requests.
+    pub fn forward_ref<T: ModuleFactory + 'static>(mut self) -> Self {
+        self.import_refs.push(ImportRef::of::<T>());
+        self.imports.push(async_step(|module, ctx| {
+            Box::pin(async move {
+                match ctx.modules.get(&ModuleKey::of::<T>()) {
+                    Some(built) => module.imports.push(built.clone()),
+                    None => {
+                        module.forward_refs.push(std::any::type_name::<T>());
+                        module.forward_imports.push(ForwardImport {
+                            key: ModuleKey::of::<T>(),
+                            build: ApplicationContext::build_module::<T>,
+                        });
+                    }
+                }
+                Ok(())
+            })
         }));
         self
     }
 
     pub fn export<T>(mut self) -> Self
     where
-        T: Injected + Send + Sync + 'static,
+        T: ?Sized + Send + Sync + 'static,
     {
-        self.exports.insert(TypeId::of::<Arc<T>>());
+        self.exports.insert(Token::of::<Arc<T>>());
         self
     }
 
@@ -61,7 +711,16 @@ impl Module {
     where
         T: Injected + Send + Sync + 'static,
     {
-        self.exports.insert(TypeId::of::<T>());
+        self.exports.insert(Token::of::<T>());
+        self
+    }
+
+    /// Exports the value registered with [`Module::provide_named`] under `name`.
+    pub fn export_named<T>(mut self, name: &'static str) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        self.exports.insert(Token::named::<T>(name));
         self
     }
 
@@ -69,25 +728,166 @@ impl Module {
     where
         T: Injected<Output = T> + 'static,
     {
+        self.declarations.push(Box::new(|module, _| {
+            module.graph.declare::<Arc<T>>(std::any::type_name::<T>());
+            Ok(())
+        }));
+        self.providers.push(Box::new(|module, ctx| {
+            if T::SCOPE == Scope::Singleton {
+                let graphs = imported_graphs(&module.imports, ctx);
+                module.graph.resolve::<Arc<T>>(&graphs)?;
+            }
+            Ok(())
+        }));
+        self.tokens.insert(Token::of::<Arc<T>>());
+        self
+    }
+
+    /// Provides `Arc<I>`, typically `Arc<dyn Trait>`, backed by the implementation `T`.
+    ///
+    /// `I` must implement [`Interface<T>`](crate::graph::Interface), which
+    /// `#[sept::interface]` generates for trait objects.
+    pub fn provide_as<I, T>(mut self) -> Self
+    where
+        I: ?Sized + Interface<T> + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self.declarations.push(Box::new(|module, _| {
+            module.graph.declare_as::<I, T>(std::any::type_name::<T>());
+            Ok(())
+        }));
         self.providers.push(Box::new(|module, ctx| {
-            let mut graphs = vec![&ctx.global_providers];
-            for module in &module.imports {
-                graphs.push(&module.graphed_exports);
+            if T::SCOPE == Scope::Singleton {
+                let graphs = imported_graphs(&module.imports, ctx);
+                module.graph.inject::<Arc<I>>(&graphs)?;
             }
-            module.graph.resolve::<Arc<T>>(&graphs);
+            Ok(())
         }));
-        self.tokens.insert(TypeId::of::<T>());
+        self.tokens.insert(Token::of::<Arc<I>>());
         self
     }
 
-    pub fn provide_val<T: Sync + Send + Clone>(mut self, t: T) -> Self
+    /// Contributes `T` to the multi-provider `I`, injected as `Multi<I>` or `Vec<Arc<dyn I>>`.
+    ///
+    /// Contributions are visible to importing modules without being exported.
+    pub fn provide_multi<I, T>(mut self) -> Self
     where
-        T: 'static,
+        I: ?Sized + Interface<T> + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self.declarations.push(Box::new(|module, _| {
+            module
+                .graph
+                .declare_multi::<I, T>(std::any::type_name::<T>());
+            Ok(())
+        }));
+        self.providers.push(Box::new(|module, ctx| {
+            if T::SCOPE == Scope::Singleton {
+                let graphs = imported_graphs(&module.imports, ctx);
+                module.graph.inject_contribution::<I, T>(&graphs)?;
+            }
+            Ok(())
+        }));
+        self.tokens.insert(Token::of::<Multi<I>>());
+        self
+    }
+
+    /// Provides `Arc<T>` built by `factory` from dependencies injected out of
+    /// this module's graph and its imports, e.g. `|(config,): (Value<DbConfig>,)| Pool::new(&config)`.
+    pub fn provide_factory<T, D, F>(mut self, factory: F) -> Self
+    where
+        T: Send + Sync + 'static,
+        D: Dependencies,
+        F: Fn(D) -> T + Send + Sync + 'static,
+    {
+        self.declarations.push(Box::new(|module, _| {
+            module
+                .graph
+                .declare_factory(std::any::type_name::<T>(), factory);
+            Ok(())
+        }));
+        self.providers.push(Box::new(|module, ctx| {
+            let graphs = imported_graphs(&module.imports, ctx);
+            module.graph.inject::<Arc<T>>(&graphs)?;
+            Ok(())
+        }));
+        self.tokens.insert(Token::of::<Arc<T>>());
+        self
+    }
+
+    /// Provides `Arc<T>` built by an async `factory`, e.g. a connection pool that must connect first.
+    ///
+    /// Async factories run in declaration order once imports and values are in
+    /// place, before the module's synchronous providers are constructed.
+    pub fn provide_async_factory<T, D, F, Fut, E>(mut self, factory: F) -> Self
+    where
+        T: Send + Sync + 'static,
+        D: Dependencies + 'static,
+        F: FnOnce(D) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.async_providers.push(async_step(|module, ctx| {
+            Box::pin(async move {
+                if module.graph.get_node::<Arc<T>>().is_some() {
+                    return Ok(());
+                }
+                let type_name = std::any::type_name::<T>();
+                let graphs = imported_graphs(&module.imports, ctx);
+                let deps = D::inject(&mut module.graph, &graphs)
+                    .map_err(|err| err.required_by(type_name))?;
+                let value = factory(deps).await.map_err(|err| SeptError::Factory {
+                    type_name,
+                    source: err.into(),
+                })?;
+                module.graph.provide(Arc::new(Arc::new(value)));
+                Ok(())
+            })
+        }));
+        self.tokens.insert(Token::of::<Arc<T>>());
+        self
+    }
+
+    pub fn provide_val<T>(mut self, t: T) -> Self
+    where
+        T: Sync + Send + Clone + 'static,
     {
         self.provider_vals.push(Box::new(|module, _| {
             module.graph.provide(Arc::new(t));
+            Ok(())
+        }));
+        self.tokens.insert(Token::of::<T>());
+        self
+    }
+
+    /// Provides `t` under `name`, so several values of the same type can coexist.
+    ///
+    /// Injectable fields receive it with `#[inject("name")]`.
+    pub fn provide_named<T>(mut self, name: &'static str, t: T) -> Self
+    where
+        T: Sync + Send + Clone + 'static,
+    {
+        self.provider_vals.push(Box::new(move |module, _| {
+            module.graph.provide_named(name, Arc::new(t));
+            Ok(())
+        }));
+        self.tokens.insert(Token::named::<T>(name));
+        self
+    }
+
+    /// Registers the lifecycle hooks of an `Arc<T>` provided by a factory or as a value.
+    ///
+    /// `#[injectable(hooks(...))]` cannot be used on those, so the hooks are
+    /// listed here instead, e.g.
+    /// `.with_hooks::<Pool>(|pool, hooks| hooks.on_application_shutdown(pool.clone()))`.
+    pub fn with_hooks<T>(mut self, register: fn(&Arc<T>, &mut Hooks)) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(move |module, _| {
+            module.graph.on_provided(register);
+            Ok(())
         }));
-        self.tokens.insert(TypeId::of::<T>());
         self
     }
 
@@ -96,39 +896,185 @@ impl Module {
         T: Injected<Output = T> + ServiceFactory + 'static,
     {
         self.clients.push(Box::new(|module, ctx| {
-            let mut graphs = vec![&ctx.global_providers];
-            for module in &module.imports {
-                graphs.push(&module.graphed_exports);
-            }
-            let resolved = T::resolve(&mut module.graph, &graphs);
-            module.clients.push(Arc::new(resolved));
+            let graphs = imported_graphs(&module.imports, ctx);
+            let resolved = Arc::new(T::resolve(&mut module.graph, &graphs)?);
+            module.graph.register_hooks(&resolved);
+            module.graph.provide(Arc::new(resolved.clone()));
+            module.info.routes.extend(resolved.routes());
+            module.clients.push(resolved);
+            module
+                .info
+                .clients
+                .push(std::any::type_name::<T>().to_owned());
+            Ok(())
         }));
-        self.tokens.insert(TypeId::of::<T>());
+        self.tokens.insert(Token::of::<Arc<T>>());
+        self
+    }
+
+    /// Guards every route of the module's clients with `G`, see [`Guard`](crate::guard::Guard).
+    pub fn guard<G>(mut self) -> Self
+    where
+        G: Guard + Injected<Output = G> + Send + Sync + 'static,
+    {
+        self.guards.push(guard::resolve::<G>);
+        self
+    }
+
+    /// Wraps every route of the module's clients in `I`, see [`Interceptor`].
+    pub fn interceptor<I>(mut self) -> Self
+    where
+        I: Interceptor + Injected<Output = I> + Send + Sync + 'static,
+    {
+        self.interceptors.push(interceptor::resolve::<I>);
+        self
+    }
+
+    /// Runs `P` over the path, query and body arguments of every route of the module's clients,
+    /// see [`ArgumentPipe`].
+    pub fn pipe<P>(mut self) -> Self
+    where
+        P: ArgumentPipe + Injected<Output = P> + Send + Sync + 'static,
+    {
+        self.pipes.push(pipe::resolve_argument_pipe::<P>);
+        self
+    }
+
+    /// Catches the errors of every route of the module's clients with `F`, see [`ExceptionFilter`].
+    pub fn filter<F>(mut self) -> Self
+    where
+        F: ExceptionFilter + Injected<Output = F> + Send + Sync + 'static,
+    {
+        self.filters.push(filter::resolve::<F>);
         self
     }
 
-    pub(crate) fn build(self, ctx: &mut ApplicationContext) -> ResolvedModule {
+    /// Names the module after its factory type for error reporting.
+    fn named(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub(crate) async fn build(
+        self,
+        ctx: &mut ApplicationContext,
+    ) -> Result<ResolvedModule, SeptError> {
         let mut module = ResolvedModule::new();
+        module.global = self.global;
+        let name = self.name.unwrap_or("<anonymous>");
+        module.name = name;
+        module.info = ModuleInfo::new(name);
+        module.info.global = self.global;
 
         for import in self.imports {
-            import(&mut module, ctx);
+            import(&mut module, ctx)
+                .await
+                .map_err(|err| err.in_module(name))?;
         }
-
-        for provided_val in self.provider_vals {
-            provided_val(&mut module, ctx);
+        let overridden = module.graph.apply_overrides(&ctx.overrides, &self.tokens);
+        ctx.overridden.extend(overridden);
+        for step in self
+            .hooks
+            .into_iter()
+            .chain(self.provider_vals)
+            .chain(self.declarations)
+        {
+            step(&mut module, ctx).map_err(|err| err.in_module(name))?;
         }
-
-        for provider in self.providers {
-            provider(&mut module, ctx);
+        for provider in self.async_providers {
+            provider(&mut module, ctx)
+                .await
+                .map_err(|err| err.in_module(name))?;
+        }
+        for step in self.providers.into_iter().chain(self.clients) {
+            step(&mut module, ctx).map_err(|err| err.in_module(name))?;
         }
 
-        for client in self.clients {
-            client(&mut module, ctx);
+        let graphs = imported_graphs(&module.imports, ctx);
+        let filled = match module.forward_imports.is_empty() {
+            true => module.graph.fill_lazy(&graphs),
+            false => module.graph.fill_lazy_in_scope(&graphs),
+        };
+        filled.map_err(|err| err.in_module(name))?;
+
+        module.info.imports = module.imports.iter().map(|m| m.info.name.clone()).collect();
+        module.info.forward_refs = module.forward_refs.iter().map(|r| r.to_string()).collect();
+        module.info.providers = sorted_names(self.tokens.iter())
+            .into_iter()
+            .filter(|provider| !module.info.clients.contains(provider))
+            .collect();
+        module.info.exports = sorted_names(self.exports.iter());
+        module.graphed_exports = module.graph.export(self.exports, &graphs);
+        module.module_ref = ModuleRef::new(module.graph.clone(), &graphs);
+        let guards = self
+            .guards
+            .iter()
+            .map(|guard| guard(&mut module.module_ref))
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.in_module(name))?;
+        module.module_ref.guards = Arc::new(guards);
+        // Global interceptors, pipes and filters only apply to routes, so modules without
+        // clients need not see their dependencies.
+        let (global_interceptors, global_pipes, global_filters) = match module.clients.is_empty() {
+            true => (&[][..], &[][..], &[][..]),
+            false => (&ctx.interceptors[..], &ctx.pipes[..], &ctx.filters[..]),
+        };
+        let interceptors = global_interceptors
+            .iter()
+            .chain(&self.interceptors)
+            .map(|interceptor| interceptor(&mut module.module_ref))
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.in_module(name))?;
+        module.module_ref.interceptors = Arc::new(interceptors);
+        let pipes = global_pipes
+            .iter()
+            .chain(&self.pipes)
+            .map(|pipe| pipe(&mut module.module_ref))
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.in_module(name))?;
+        module.module_ref.pipes = Arc::new(pipes);
+        let filters = self
+            .filters
+            .iter()
+            .chain(global_filters)
+            .map(|filter| filter(&mut module.module_ref))
+            .collect::<Result<_, _>>()
+            .map_err(|err| err.in_module(name))?;
+        module.module_ref.filters = Arc::new(filters);
+        for client in &module.clients {
+            for guard in client.guards() {
+                guard(&mut module.module_ref).map_err(|err| err.in_module(name))?;
+            }
+            for interceptor in client.interceptors() {
+                interceptor(&mut module.module_ref).map_err(|err| err.in_module(name))?;
+            }
+            for pipe in client.pipes() {
+                pipe(&mut module.module_ref).map_err(|err| err.in_module(name))?;
+            }
+            for filter in client.filters() {
+                filter(&mut module.module_ref).map_err(|err| err.in_module(name))?;
+            }
         }
+        Ok(module)
+    }
+}
 
-        module.graphed_exports = module.graph.filter_by(self.exports);
-        module
+/// Collects the graphs visible to a module besides its own: globals, then imported exports.
+fn imported_graphs<'a>(
+    imports: &'a [Arc<ResolvedModule>],
+    ctx: &'a ApplicationContext,
+) -> Vec<&'a Graph> {
+    let mut graphs = vec![&ctx.global_providers];
+    for module in imports {
+        graphs.push(&module.graphed_exports);
     }
+    graphs
+}
+
+fn sorted_names<'a>(tokens: impl Iterator<Item = &'a Token>) -> Vec<String> {
+    let mut names: Vec<_> = tokens.map(inspect::provided).collect();
+    names.sort();
+    names
 }
 
 pub trait ModuleFactory: Sized {
@@ -137,19 +1083,32 @@ pub trait ModuleFactory: Sized {
 
 #[derive(Clone)]
 pub(crate) struct ResolvedModule {
+    name: &'static str,
     pub(crate) graph: Graph,
     pub(crate) imports: Vec<Arc<Self>>,
+    pub(crate) forward_refs: Vec<&'static str>,
+    /// The imports of `forward_refs`, resolved by `ApplicationContext::resolve_forward_refs`.
+    forward_imports: Vec<ForwardImport>,
     graphed_exports: Graph,
+    global: bool,
     pub(crate) clients: Vec<Arc<dyn ServiceFactory>>,
+    pub(crate) module_ref: ModuleRef,
+    pub(crate) info: ModuleInfo,
 }
 
 impl ResolvedModule {
     pub fn new() -> Self {
         Self {
+            name: "<anonymous>",
             graph: Graph::new(),
             imports: Vec::new(),
+            forward_refs: Vec::new(),
+            forward_imports: Vec::new(),
             graphed_exports: Graph::new(),
+            global: false,
             clients: Vec::new(),
+            module_ref: ModuleRef::default(),
+            info: ModuleInfo::default(),
         }
     }
 }
@@ -158,32 +1117,34 @@ impl ResolvedModule {
 mod tests {
     use super::*;
     use crate as sept;
-    use crate::sept_module::{Module, ServiceConfig, ServiceFactory};
+    use crate::graph::{Lazy, Value};
+    use crate::sept_module::{Module, ModuleRef, Scoped, ServiceConfig, ServiceFactory};
     use crate::Injectable;
 
     fn get_empty_ctx() -> ApplicationContext {
-        ApplicationContext {
-            global_providers: Graph::new(),
-            modules: HashMap::new(),
-        }
+        ApplicationContext::new(Graph::new())
     }
 
-    #[test]
-    fn test_client_is_reachable() {
+    #[actix_rt::test]
+    async fn test_client_is_reachable() {
         #[derive(Clone, Injectable)]
         struct TestInjectable;
 
         impl ServiceFactory for TestInjectable {
-            fn register(&self, _: &mut ServiceConfig) {}
+            fn register(&self, _: &mut ServiceConfig, _: &ModuleRef) {}
         }
 
         let mut ctx = get_empty_ctx();
-        let resolved = Module::new().client::<TestInjectable>().build(&mut ctx);
+        let resolved = Module::new()
+            .client::<TestInjectable>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
         assert_eq!(resolved.clients.len(), 1);
     }
 
-    #[test]
-    fn test_imported_provider_is_reachable() {
+    #[actix_rt::test]
+    async fn test_imported_provider_is_reachable() {
         #[derive(Clone, Injectable)]
         struct TestInjectable;
 
@@ -197,7 +1158,11 @@ mod tests {
         }
 
         let mut ctx = get_empty_ctx();
-        let resolved = Module::new().import::<ExportingModule>().build(&mut ctx);
+        let resolved = Module::new()
+            .import::<ExportingModule>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
         assert_eq!(resolved.imports.len(), 1);
 
         assert!(resolved.imports[0]
@@ -205,4 +1170,657 @@ mod tests {
             .get_node::<Arc<TestInjectable>>()
             .is_some());
     }
+
+    #[actix_rt::test]
+    async fn test_missing_provider_is_reported() {
+        #[derive(Clone)]
+        struct Config;
+
+        #[derive(Clone, Injectable)]
+        struct TestInjectable {
+            _config: Arc<Config>,
+        }
+
+        struct BrokenModule;
+        impl ModuleFactory for BrokenModule {
+            fn get_module() -> Module {
+                Module::new().provide::<TestInjectable>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let built = Module::new().import::<BrokenModule>().build(&mut ctx).await;
+        let err = built.err().expect("expected a missing provider error");
+        let SeptError::MissingProvider {
+            type_name,
+            module,
+            chain,
+            ..
+        } = &err
+        else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(*type_name, std::any::type_name::<Arc<Config>>());
+        assert_eq!(*module, Some(std::any::type_name::<BrokenModule>()));
+        assert!(chain[0].ends_with("TestInjectable"));
+    }
+
+    struct CycleA;
+    struct CycleB;
+
+    impl ModuleFactory for CycleA {
+        fn get_module() -> Module {
+            Module::new().import::<CycleB>()
+        }
+    }
+
+    impl ModuleFactory for CycleB {
+        fn get_module() -> Module {
+            Module::new().import::<CycleA>()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_circular_import_is_reported() {
+        let mut ctx = get_empty_ctx();
+        let built = ctx.build_module::<CycleA>().await;
+        let err = built.err().expect("expected a circular import error");
+        let SeptError::CircularImport { path } = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(
+            *path,
+            [
+                std::any::type_name::<CycleA>(),
+                std::any::type_name::<CycleB>(),
+                std::any::type_name::<CycleA>(),
+            ]
+        );
+        assert!(ctx.building.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn test_forward_ref_breaks_cycle() {
+        struct ForwardA;
+        struct ForwardB;
+
+        impl ModuleFactory for ForwardA {
+            fn get_module() -> Module {
+                Module::new().import::<ForwardB>()
+            }
+        }
+
+        impl ModuleFactory for ForwardB {
+            fn get_module() -> Module {
+                Module::new().forward_ref::<ForwardA>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = ctx.build_module::<ForwardA>().await.unwrap();
+        assert_eq!(
+            resolved.imports[0].forward_refs,
+            vec![std::any::type_name::<ForwardA>()]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_forward_ref_exports_resolve_in_either_build_order() {
+        #[derive(Clone, Injectable)]
+        struct Users {
+            orders: sept::graph::Lazy<Arc<Orders>>,
+            stamp: sept::graph::Lazy<Arc<Stamp>>,
+        }
+
+        #[derive(Clone, Injectable)]
+        #[injectable(scope = "transient")]
+        struct Stamp {
+            orders: sept::graph::Lazy<Arc<Orders>>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Orders {
+            _users: Arc<Users>,
+        }
+
+        struct UsersModule;
+        struct OrdersModule;
+
+        impl ModuleFactory for UsersModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .forward_ref::<OrdersModule>()
+                    .provide::<Users>()
+                    .provide::<Stamp>()
+                    .export::<Users>()
+            }
+        }
+
+        impl ModuleFactory for OrdersModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import::<UsersModule>()
+                    .provide::<Orders>()
+                    .export::<Orders>()
+            }
+        }
+
+        fn check(ctx: &ApplicationContext) {
+            let users = &ctx.modules[&ModuleKey::of::<UsersModule>()];
+            let orders = &ctx.modules[&ModuleKey::of::<OrdersModule>()];
+            let orders = orders.graph.get_node::<Arc<Orders>>().unwrap();
+            let provided = users.graph.get_node::<Arc<Users>>().unwrap();
+            assert!(Arc::ptr_eq(&provided.orders, orders));
+            assert!(Arc::ptr_eq(&provided.stamp.orders, orders));
+            assert!(Arc::ptr_eq(
+                &users.module_ref.get::<Arc<Orders>>().unwrap(),
+                orders
+            ));
+        }
+
+        let mut ctx = get_empty_ctx();
+        ctx.build_root::<UsersModule>().await.unwrap();
+        check(&ctx);
+        let mut ctx = get_empty_ctx();
+        ctx.build_root::<OrdersModule>().await.unwrap();
+        check(&ctx);
+    }
+
+    #[actix_rt::test]
+    async fn test_lazy_is_filled_for_providers_built_after_their_module() {
+        #[derive(Clone, Injectable)]
+        struct Ledger;
+
+        #[derive(Clone, Injectable)]
+        #[injectable(scope = "request")]
+        struct Entry {
+            #[inject(lazy)]
+            ledger: Lazy<Arc<Ledger>>,
+        }
+
+        #[derive(Clone, Injectable)]
+        #[injectable(scope = "request")]
+        struct Receipt {
+            #[inject(lazy)]
+            entry: Lazy<Arc<Entry>>,
+            #[inject(lazy)]
+            ledger: Lazy<Arc<Ledger>>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Report {
+            #[inject(lazy)]
+            ledger: Lazy<Arc<Ledger>>,
+        }
+
+        #[derive(Clone, Injectable)]
+        #[injectable(scope = "transient")]
+        struct Recursive {
+            #[inject(lazy)]
+            _next: Lazy<Arc<Self>>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Ledger>()
+            .provide::<Entry>()
+            .provide::<Receipt>()
+            .provide::<Recursive>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let ledger = resolved.graph.get_node::<Arc<Ledger>>().unwrap();
+        let report = resolved.module_ref.get_or_build::<Report>().unwrap();
+        assert!(Arc::ptr_eq(&report.ledger, ledger));
+
+        let req = actix_web::test::TestRequest::default()
+            .app_data(actix_web::web::Data::new(resolved.module_ref.clone()))
+            .to_http_request();
+        let receipt = Scoped::<Receipt>::extract(&req).await.unwrap();
+        assert!(Arc::ptr_eq(&receipt.ledger, ledger));
+        assert!(Arc::ptr_eq(&receipt.entry.ledger, ledger));
+
+        let Err(err) = resolved.module_ref.get::<Arc<Recursive>>() else {
+            panic!("expected a circular dependency error");
+        };
+        assert!(
+            matches!(err, SeptError::CircularDependency { .. }),
+            "{}",
+            err
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_provider_scopes() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static BUILT: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone)]
+        struct Transient {
+            id: usize,
+        }
+
+        impl Injected for Transient {
+            type Output = Self;
+            const SCOPE: Scope = Scope::Transient;
+
+            fn resolve(_: &mut Graph, _: &[&Graph]) -> Result<Self, SeptError> {
+                Ok(Self {
+                    id: BUILT.fetch_add(1, Ordering::SeqCst),
+                })
+            }
+        }
+
+        #[derive(Clone, Injectable)]
+        #[injectable(scope = "request")]
+        struct RequestContext {
+            request: Arc<RequestInfo>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Consumer {
+            first: Arc<Transient>,
+            second: Arc<Transient>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Transient>()
+            .provide::<RequestContext>()
+            .provide::<Consumer>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let consumer = resolved.graph.get_node::<Arc<Consumer>>().unwrap();
+        assert_ne!(consumer.first.id, consumer.second.id);
+        let mut graph = Graph::new();
+        let first = graph.resolve::<Arc<Transient>>(&[]).unwrap();
+        let second = graph.resolve::<Arc<Transient>>(&[]).unwrap();
+        assert_ne!(first.id, second.id);
+
+        let req = actix_web::test::TestRequest::with_uri("/users")
+            .app_data(actix_web::web::Data::new(resolved.module_ref.clone()))
+            .to_http_request();
+        let extracted = Scoped::<Consumer>::extract(&req).await.unwrap();
+        assert!(Arc::ptr_eq(&extracted.into_inner(), consumer));
+        let first = Scoped::<Transient>::extract(&req).await.unwrap();
+        let second = Scoped::<Transient>::extract(&req).await.unwrap();
+        assert_ne!(first.id, second.id);
+        let first = Scoped::<RequestContext>::extract(&req).await.unwrap();
+        let second = Scoped::<RequestContext>::extract(&req).await.unwrap();
+        assert!(Arc::ptr_eq(&first.into_inner(), &second.into_inner()));
+        assert_eq!(
+            Scoped::<RequestContext>::extract(&req)
+                .await
+                .unwrap()
+                .request
+                .uri
+                .path(),
+            "/users"
+        );
+    }
+
+    #[derive(Clone, Injectable)]
+    #[injectable(scope = "request")]
+    struct RequestLog {
+        request: Arc<RequestInfo>,
+    }
+
+    #[derive(Clone, Injectable)]
+    #[injectable(scope = "request")]
+    struct Trail {
+        log: Arc<RequestLog>,
+    }
+
+    #[derive(Clone, Injectable)]
+    #[injectable(scope = "request")]
+    struct Caller {
+        log: Arc<RequestLog>,
+    }
+
+    #[derive(Clone, Injectable)]
+    #[injectable(scope = "request")]
+    struct Unreachable {
+        _log: Arc<RequestLog>,
+        _missing: Value<u32>,
+    }
+
+    #[crate::module]
+    #[providers(RequestLog, Trail, Caller, Unreachable)]
+    #[exports(Trail, Caller, Unreachable)]
+    struct AuditModule;
+
+    #[derive(Clone, Injectable)]
+    struct AuditClient;
+
+    #[crate::client("/audit")]
+    impl AuditClient {
+        #[get("")]
+        async fn show(_client: Self, trail: Scoped<Trail>, caller: Scoped<Caller>) -> String {
+            assert!(Arc::ptr_eq(&trail.log, &caller.log));
+            trail.log.request.uri.path().to_owned()
+        }
+
+        #[get("/unreachable")]
+        async fn unreachable(_client: Self, _unreachable: Scoped<Unreachable>) -> String {
+            String::from("reached")
+        }
+    }
+
+    #[crate::module]
+    #[imports(AuditModule)]
+    #[clients(AuditClient)]
+    struct AuditedModule;
+
+    #[actix_rt::test]
+    async fn test_exported_request_scoped_providers_are_built_per_request() {
+        use crate::sept_application::SeptApplication;
+        use crate::testing::TestApp;
+
+        let app = TestApp::create::<AuditedModule>(SeptApplication::default())
+            .await
+            .unwrap();
+        let shown = app.get("/audit").send().await.assert_status(200);
+        assert_eq!(shown.text(), "/audit");
+        let failed = app.get("/audit/unreachable").send().await;
+        assert_eq!(
+            failed.assert_status(500).json::<serde_json::Value>(),
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Internal Server Error",
+                "status": 500,
+                "instance": "/audit/unreachable",
+            })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn test_request_scoped_provider_is_rejected_outside_request() {
+        #[derive(Clone, Injectable)]
+        #[injectable(scope = "request")]
+        struct RequestContext;
+
+        #[derive(Clone, Injectable)]
+        struct Singleton {
+            _context: Arc<RequestContext>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let result = Module::new()
+            .provide::<RequestContext>()
+            .provide::<Singleton>()
+            .build(&mut ctx)
+            .await;
+        let err = result.err().expect("expected a request scope error");
+        let SeptError::RequestScoped { chain, .. } = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert!(chain[0].ends_with("Singleton"));
+    }
+
+    #[derive(Clone, Injectable)]
+    struct AppConfig;
+
+    struct ConfigModule;
+
+    impl ModuleFactory for ConfigModule {
+        fn get_module() -> Module {
+            Module::new()
+                .global()
+                .provide::<AppConfig>()
+                .export::<AppConfig>()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct UsesConfig {
+        _config: Arc<AppConfig>,
+    }
+
+    struct FeatureModule;
+
+    impl ModuleFactory for FeatureModule {
+        fn get_module() -> Module {
+            Module::new().provide::<UsesConfig>()
+        }
+    }
+
+    struct GlobalAppModule;
+
+    impl ModuleFactory for GlobalAppModule {
+        fn get_module() -> Module {
+            Module::new()
+                .import::<FeatureModule>()
+                .import::<ConfigModule>()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_global_module_exports_are_visible_without_import() {
+        let mut ctx = get_empty_ctx();
+        let resolved = ctx.build_root::<GlobalAppModule>().await.unwrap();
+        assert!(resolved.imports[0]
+            .graph
+            .get_node::<Arc<UsesConfig>>()
+            .is_some());
+        assert!(ctx.global_providers.get_node::<Arc<AppConfig>>().is_some());
+    }
+
+    #[derive(Clone, Hash)]
+    struct DbOptions {
+        url: &'static str,
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Connection {
+        options: Arc<DbOptions>,
+    }
+
+    struct DbModule;
+
+    impl DbModule {
+        fn for_root(options: DbOptions) -> DynamicModule {
+            DynamicModule::new::<Self>(
+                &options,
+                Module::new()
+                    .provide_val(Arc::new(options.clone()))
+                    .provide::<Connection>()
+                    .export::<Connection>(),
+            )
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct UsersRepository {
+        connection: Arc<Connection>,
+    }
+
+    #[crate::module]
+    #[imports(DbModule::for_root(DbOptions { url: "postgres://app" }))]
+    #[providers(UsersRepository)]
+    #[exports(UsersRepository)]
+    struct UsersModule;
+
+    #[derive(Clone, Injectable)]
+    struct OrdersRepository {
+        connection: Arc<Connection>,
+    }
+
+    #[crate::module]
+    #[imports(DbModule::for_root(DbOptions { url: "postgres://app" }))]
+    #[providers(OrdersRepository)]
+    #[exports(OrdersRepository)]
+    struct OrdersModule;
+
+    #[actix_rt::test]
+    async fn test_dynamic_module_is_configured_and_deduplicated() {
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<UsersModule>()
+            .import::<OrdersModule>()
+            .import_dynamic(
+                DbModule::for_root(DbOptions {
+                    url: "postgres://replica",
+                })
+                .keyed("replica"),
+            )
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let users = resolved.imports[0]
+            .graph
+            .get_node::<Arc<UsersRepository>>()
+            .unwrap();
+        let orders = resolved.imports[1]
+            .graph
+            .get_node::<Arc<OrdersRepository>>()
+            .unwrap();
+        assert_eq!(users.connection.options.url, "postgres://app");
+        assert!(Arc::ptr_eq(&users.connection, &orders.connection));
+        assert_eq!(ctx.modules.len(), 4);
+    }
+
+    #[actix_rt::test]
+    async fn test_dynamic_modules_are_keyed_by_their_options() {
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<UsersModule>()
+            .import_dynamic(DbModule::for_root(DbOptions {
+                url: "postgres://replica",
+            }))
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let connection = resolved.imports[1]
+            .graph
+            .get_node::<Arc<Connection>>()
+            .unwrap();
+        assert_eq!(connection.options.url, "postgres://replica");
+
+        let mut ctx = get_empty_ctx();
+        let built = Module::new()
+            .import_dynamic(
+                DbModule::for_root(DbOptions {
+                    url: "postgres://a",
+                })
+                .keyed("db"),
+            )
+            .import_dynamic(
+                DbModule::for_root(DbOptions {
+                    url: "postgres://b",
+                })
+                .keyed("db"),
+            )
+            .build(&mut ctx)
+            .await;
+        let err = built.err().expect("expected a dynamic module conflict");
+        let SeptError::DynamicModuleConflict { key, .. } = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(*key, "db");
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Clock;
+
+    struct ClockModule;
+
+    impl ModuleFactory for ClockModule {
+        fn get_module() -> Module {
+            Module::new().provide::<Clock>().export::<Clock>()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Stamp {
+        _clock: Arc<Clock>,
+    }
+
+    impl crate::interceptor::Interceptor for Stamp {
+        async fn intercept(
+            &self,
+            _: &crate::guard::ExecutionContext,
+            next: crate::interceptor::Next,
+        ) -> Result<crate::interceptor::Handled, SeptError> {
+            let handled = next.handle().await?;
+            Ok(handled.map(|body: String| format!("{} (stamped)", body)))
+        }
+    }
+
+    #[derive(Debug)]
+    struct Unposted(u32);
+
+    impl std::fmt::Display for Unposted {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "no entry {}", self.0)
+        }
+    }
+
+    impl actix_web::ResponseError for Unposted {}
+
+    #[derive(Clone, Injectable)]
+    struct LateFilter {
+        _clock: Arc<Clock>,
+    }
+
+    #[crate::catch(Unposted)]
+    impl crate::filter::ExceptionFilter for LateFilter {
+        async fn catch(
+            &self,
+            exception: &Unposted,
+            _: &crate::guard::ExecutionContext,
+        ) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::NotFound().body(format!("{} yet", exception))
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Ledger;
+
+    struct LedgerModule;
+
+    impl ModuleFactory for LedgerModule {
+        fn get_module() -> Module {
+            Module::new().provide::<Ledger>().export::<Ledger>()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct LedgerClient {
+        _ledger: Arc<Ledger>,
+    }
+
+    #[crate::client("/ledger")]
+    impl LedgerClient {
+        #[get("")]
+        async fn show(_client: Self) -> String {
+            String::from("balanced")
+        }
+
+        #[get("/{id}")]
+        async fn entry(_client: Self, id: actix_web::web::Path<u32>) -> Result<String, Unposted> {
+            Err(Unposted(id.into_inner()))
+        }
+    }
+
+    #[crate::module]
+    #[imports(ClockModule, LedgerModule)]
+    #[clients(LedgerClient)]
+    struct StampedModule;
+
+    #[actix_rt::test]
+    async fn test_global_enhancers_are_resolved_only_for_modules_with_clients() {
+        use crate::sept_application::SeptApplication;
+        use crate::testing::TestApp;
+
+        let application = SeptApplication::default()
+            .use_global_interceptor::<Stamp>()
+            .use_global_filters::<LateFilter>();
+        let app = TestApp::create::<StampedModule>(application).await.unwrap();
+        let shown = app.get("/ledger").send().await.assert_status(200);
+        assert_eq!(shown.text(), "balanced (stamped)");
+        let entry = app.get("/ledger/4").send().await.assert_status(404);
+        assert_eq!(entry.text(), "no entry 4 yet");
+    }
 }
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Stops the running application gracefully.
+///
+/// Every module can inject it as `Arc<ShutdownHandle>`. Calling
+/// [`ShutdownHandle::shutdown`] runs the `BeforeApplicationShutdown` hooks,
+/// lets in-flight requests drain within `SeptConfig::shutdown_timeout`, then
+/// runs the `OnApplicationShutdown` hooks.
+#[derive(Clone, Debug, Default)]
+pub struct ShutdownHandle {
+    requested: Arc<Notify>,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests a graceful shutdown of the application.
+    pub fn shutdown(&self) {
+        self.requested.notify_one();
+    }
+
+    /// Resolves once a shutdown was requested, or a termination signal received if `signals` is set.
+    pub(crate) async fn requested(&self, signals: bool) {
+        if signals {
+            futures_util::future::select(
+                Box::pin(self.requested.notified()),
+                Box::pin(termination_signal()),
+            )
+            .await;
+        } else {
+            self.requested.notified().await;
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn termination_signal() {
+    use actix_rt::signal::unix::{signal, SignalKind};
+
+    let (Ok(mut interrupt), Ok(mut terminate)) = (
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::terminate()),
+    ) else {
+        tracing::warn!("failed to listen for termination signals");
+        return std::future::pending().await;
+    };
+    futures_util::future::select(Box::pin(interrupt.recv()), Box::pin(terminate.recv())).await;
+    tracing::info!("termination signal received, shutting down");
+}
+
+#[cfg(not(unix))]
+async fn termination_signal() {
+    if actix_rt::signal::ctrl_c().await.is_err() {
+        tracing::warn!("failed to listen for termination signals");
+        return std::future::pending().await;
+    }
+    tracing::info!("termination signal received, shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::graph::Graph;
+    use crate::sept_module::{ApplicationContext, Module};
+    use crate::Injectable;
+
+    #[actix_rt::test]
+    async fn test_shutdown_handle_is_injectable() {
+        #[derive(Clone, Injectable)]
+        struct Supervisor {
+            shutdown: Arc<ShutdownHandle>,
+        }
+
+        let handle = ShutdownHandle::new();
+        let mut globals = Graph::new();
+        globals.provide(Arc::new(Arc::new(handle.clone())));
+        let mut ctx = ApplicationContext::new(globals);
+        let resolved = Module::new()
+            .provide::<Supervisor>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let supervisor = resolved.graph.get_node::<Arc<Supervisor>>().unwrap();
+        supervisor.shutdown.shutdown();
+        actix_rt::time::timeout(std::time::Duration::from_secs(1), handle.requested(false))
+            .await
+            .unwrap();
+    }
+}
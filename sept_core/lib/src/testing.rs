@@ -0,0 +1,478 @@
+use crate::error::SeptError;
+use crate::sept_application::{SeptApplication, SeptConfig, SeptContext};
+use crate::sept_module::{ApplicationContext, ModuleFactory};
+use crate::shutdown::ShutdownHandle;
+use actix_http::header::{HeaderMap, TryIntoHeaderPair};
+use actix_http::{Method, Request, StatusCode};
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::web::Bytes;
+use futures_util::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::TypeId;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// Entry point for building modules in tests.
+pub struct Test;
+
+impl Test {
+    /// Starts building the module graph rooted at `T`, with providers and modules overridable.
+    pub fn create_testing_module<T: ModuleFactory + 'static>() -> TestingModuleBuilder<T> {
+        TestingModuleBuilder {
+            ctx: SeptConfig::default().register_globals(&ShutdownHandle::new(), None),
+            root: PhantomData,
+        }
+    }
+}
+
+/// Builds a testing module, see [`Test::create_testing_module`].
+pub struct TestingModuleBuilder<T> {
+    ctx: ApplicationContext,
+    root: PhantomData<T>,
+}
+
+impl<T: ModuleFactory + 'static> TestingModuleBuilder<T> {
+    /// Replaces the `Arc<P>` provider wherever it is provided, e.g. with a mock.
+    ///
+    /// This covers `provide`, `provide_as`, the factory providers and values
+    /// provided as `Arc<P>`. Other values are replaced with
+    /// [`override_value`](Self::override_value), named ones with
+    /// [`override_named`](Self::override_named). Multi-providers cannot be
+    /// overridden, and [`compile`](Self::compile) fails with
+    /// `SeptError::UnusedOverride` if an override replaces no provider.
+    pub fn override_provider<P>(self) -> OverrideProvider<T, P>
+    where
+        P: ?Sized + Send + Sync + 'static,
+    {
+        OverrideProvider {
+            builder: self,
+            provider: PhantomData,
+        }
+    }
+
+    /// Replaces the value `V` provided with `Module::provide_val`.
+    pub fn override_value<V>(self) -> OverrideValue<T, V>
+    where
+        V: Send + Sync + 'static,
+    {
+        OverrideValue {
+            builder: self,
+            name: None,
+            value: PhantomData,
+        }
+    }
+
+    /// Replaces the value `V` provided under `name` with `Module::provide_named`.
+    pub fn override_named<V>(self, name: &'static str) -> OverrideValue<T, V>
+    where
+        V: Send + Sync + 'static,
+    {
+        OverrideValue {
+            builder: self,
+            name: Some(name),
+            value: PhantomData,
+        }
+    }
+
+    /// Builds `R` wherever `M` is imported.
+    pub fn override_module<M, R>(mut self) -> Self
+    where
+        M: ModuleFactory + 'static,
+        R: ModuleFactory + 'static,
+    {
+        self.ctx
+            .module_overrides
+            .insert(TypeId::of::<M>(), R::get_module);
+        self
+    }
+
+    /// Builds the module graph and runs its startup hooks.
+    pub async fn compile(self) -> Result<SeptContext, SeptError> {
+        SeptContext::build::<T>(self.ctx).await
+    }
+}
+
+/// Chooses the replacement of an overridden provider.
+pub struct OverrideProvider<T, P: ?Sized> {
+    builder: TestingModuleBuilder<T>,
+    provider: PhantomData<fn() -> Arc<P>>,
+}
+
+impl<T, P> OverrideProvider<T, P>
+where
+    T: ModuleFactory + 'static,
+    P: ?Sized + Send + Sync + 'static,
+{
+    /// Uses `value`, e.g. `mock` or `Arc::new(mock) as Arc<dyn Repository>`, as the provider.
+    pub fn use_value(mut self, value: impl Into<Arc<P>>) -> TestingModuleBuilder<T> {
+        let value: Arc<P> = value.into();
+        self.builder.ctx.overrides.provide(Arc::new(value));
+        self.builder
+    }
+}
+
+/// Chooses the replacement of an overridden value.
+pub struct OverrideValue<T, V> {
+    builder: TestingModuleBuilder<T>,
+    name: Option<&'static str>,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<T, V> OverrideValue<T, V>
+where
+    T: ModuleFactory + 'static,
+    V: Send + Sync + 'static,
+{
+    pub fn use_value(mut self, value: V) -> TestingModuleBuilder<T> {
+        let overrides = &mut self.builder.ctx.overrides;
+        match self.name {
+            Some(name) => overrides.provide_named(name, Arc::new(value)),
+            None => overrides.provide(Arc::new(value)),
+        };
+        self.builder
+    }
+}
+
+type CallService = Rc<dyn Fn(Request) -> LocalBoxFuture<'static, TestResponse>>;
+
+/// An application served in-process, for end-to-end tests of clients without binding a port.
+///
+/// It is wired exactly like `SeptApplication::init`, CORS and instrumentation included.
+pub struct TestApp {
+    call: CallService,
+    context: SeptContext,
+}
+
+impl TestApp {
+    /// Builds the module graph rooted at `T` with the configuration of `application`.
+    pub async fn create<T: ModuleFactory + 'static>(
+        application: SeptApplication,
+    ) -> Result<Self, SeptError> {
+        application.test_app::<T>().await
+    }
+
+    /// Serves a context built by [`TestingModuleBuilder::compile`], keeping its overrides.
+    pub async fn from_context(application: SeptApplication, context: SeptContext) -> Self {
+        application.test_app_with(context).await
+    }
+
+    pub(crate) async fn new<F, B>(app: actix_web::App<F>, context: SeptContext) -> Self
+    where
+        F: ServiceFactory<
+                ServiceRequest,
+                Config = (),
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        B: MessageBody + 'static,
+    {
+        let service = Rc::new(actix_web::test::init_service(app).await);
+        let call: CallService = Rc::new(move |req| {
+            let service = service.clone();
+            Box::pin(async move {
+                match service.call(req).await {
+                    Ok(res) => TestResponse::new(
+                        res.status(),
+                        res.headers().clone(),
+                        actix_web::test::read_body(res).await,
+                    ),
+                    Err(err) => {
+                        let res = err.error_response();
+                        TestResponse::new(
+                            res.status(),
+                            res.headers().clone(),
+                            actix_web::body::to_bytes(res.into_body())
+                                .await
+                                .unwrap_or_default(),
+                        )
+                    }
+                }
+            })
+        });
+        Self { call, context }
+    }
+
+    /// The built module graph, for fetching providers alongside requests.
+    pub fn context(&self) -> &SeptContext {
+        &self.context
+    }
+
+    pub fn request(&self, method: Method, path: &str) -> TestRequest {
+        TestRequest {
+            call: self.call.clone(),
+            request: actix_web::test::TestRequest::default()
+                .method(method)
+                .uri(path),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest {
+        self.request(Method::DELETE, path)
+    }
+}
+
+/// A request to a [`TestApp`], sent with [`TestRequest::send`].
+pub struct TestRequest {
+    call: CallService,
+    request: actix_web::test::TestRequest,
+}
+
+impl TestRequest {
+    pub fn header(mut self, header: impl TryIntoHeaderPair) -> Self {
+        self.request = self.request.insert_header(header);
+        self
+    }
+
+    /// Serializes `body` as the JSON request body.
+    pub fn json(mut self, body: &impl Serialize) -> Self {
+        self.request = self.request.set_json(body);
+        self
+    }
+
+    pub async fn send(self) -> TestResponse {
+        (self.call)(self.request.to_request()).await
+    }
+}
+
+/// The status, headers and body of a response from a [`TestApp`].
+#[derive(Debug)]
+pub struct TestResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl TestResponse {
+    fn new(status: StatusCode, headers: HeaderMap, body: Bytes) -> Self {
+        Self {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    /// Panics, showing the body, unless the response has status `status`.
+    #[track_caller]
+    pub fn assert_status(self, status: u16) -> Self {
+        assert_eq!(
+            self.status.as_u16(),
+            status,
+            "unexpected status, body: {}",
+            String::from_utf8_lossy(&self.body)
+        );
+        self
+    }
+
+    /// Deserializes the JSON body, panicking if it is not a valid `T`.
+    #[track_caller]
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|err| {
+            panic!(
+                "invalid JSON body ({}): {}",
+                err,
+                String::from_utf8_lossy(&self.body)
+            )
+        })
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as sept;
+    use crate::sept_module::Module;
+    use crate::Injectable;
+
+    #[derive(Clone, Injectable)]
+    struct AuditLog;
+
+    struct AuditModule;
+
+    impl ModuleFactory for AuditModule {
+        fn get_module() -> Module {
+            Module::new().provide::<AuditLog>()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct UsersClient;
+
+    #[crate::client("/users")]
+    impl UsersClient {
+        #[get("")]
+        async fn list(_client: Self) -> actix_web::HttpResponse {
+            actix_web::HttpResponse::Ok().json(serde_json::json!([{ "name": "ada" }]))
+        }
+    }
+
+    #[crate::module]
+    #[clients(UsersClient)]
+    struct UsersHttpModule;
+
+    #[crate::interface]
+    trait Mailer: Send + Sync {
+        fn transport(&self) -> &'static str;
+    }
+
+    #[derive(Clone, Injectable)]
+    struct SmtpMailer;
+
+    impl Mailer for SmtpMailer {
+        fn transport(&self) -> &'static str {
+            "smtp"
+        }
+    }
+
+    struct MockMailer;
+
+    impl Mailer for MockMailer {
+        fn transport(&self) -> &'static str {
+            "mock"
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct Signup {
+        mailer: Arc<dyn Mailer>,
+    }
+
+    #[crate::module]
+    #[imports(AuditModule)]
+    #[providers(SmtpMailer as dyn Mailer, Signup)]
+    struct SignupModule;
+
+    struct StubAuditModule;
+
+    impl ModuleFactory for StubAuditModule {
+        fn get_module() -> Module {
+            Module::new()
+        }
+    }
+
+    #[actix_rt::test]
+    async fn test_testing_module_overrides_providers_and_modules() {
+        let module = Test::create_testing_module::<SignupModule>()
+            .override_provider::<dyn Mailer>()
+            .use_value(Arc::new(MockMailer) as Arc<dyn Mailer>)
+            .override_module::<AuditModule, StubAuditModule>()
+            .compile()
+            .await
+            .unwrap();
+        let signup = module.get::<Arc<Signup>>().unwrap();
+        assert_eq!(signup.mailer.transport(), "mock");
+        let err = module
+            .get::<Arc<AuditLog>>()
+            .err()
+            .expect("expected a missing provider error");
+        assert!(matches!(err, SeptError::MissingProvider { .. }), "{}", err);
+    }
+
+    #[actix_rt::test]
+    async fn test_testing_module_overrides_values_and_rejects_unused_overrides() {
+        #[derive(Clone, Injectable)]
+        struct Database {
+            pool_size: usize,
+            #[inject("primary")]
+            url: String,
+        }
+
+        struct SettingsModule;
+
+        impl ModuleFactory for SettingsModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .provide_val(4_usize)
+                    .provide_named("primary", "postgres://primary".to_string())
+                    .provide::<Database>()
+            }
+        }
+
+        let module = Test::create_testing_module::<SettingsModule>()
+            .override_value::<usize>()
+            .use_value(8)
+            .override_named::<String>("primary")
+            .use_value("postgres://test".to_string())
+            .compile()
+            .await
+            .unwrap();
+        let database = module.get::<Arc<Database>>().unwrap();
+        assert_eq!(database.pool_size, 8);
+        assert_eq!(database.url, "postgres://test");
+
+        let compiled = Test::create_testing_module::<SettingsModule>()
+            .override_named::<String>("replica")
+            .use_value("postgres://test".to_string())
+            .compile()
+            .await;
+        let err = compiled.err().expect("expected an unused override error");
+        let SeptError::UnusedOverride { name, .. } = &err else {
+            panic!("unexpected error: {}", err);
+        };
+        assert_eq!(*name, Some("replica"));
+    }
+
+    #[crate::go_test(
+        module = SignupModule,
+        override_provider(dyn Mailer = Arc::new(MockMailer) as Arc<dyn Mailer>),
+        override_module(AuditModule = StubAuditModule)
+    )]
+    async fn test_go_test_injects_testing_module_providers(signup: Arc<Signup>) {
+        assert_eq!(signup.mailer.transport(), "mock");
+    }
+
+    #[actix_rt::test]
+    async fn test_test_app_serves_clients_in_process() {
+        let app = TestApp::create::<UsersHttpModule>(SeptApplication::default())
+            .await
+            .unwrap();
+        let response = app
+            .get("/users")
+            .header(("origin", "http://localhost:3000"))
+            .send()
+            .await
+            .assert_status(200);
+        assert!(response
+            .headers()
+            .contains_key("access-control-allow-origin"));
+        let users: serde_json::Value = response.json();
+        assert_eq!(users[0]["name"], "ada");
+        app.get("/missing").send().await.assert_status(404);
+        app.context().get::<Arc<UsersClient>>().unwrap();
+    }
+}